@@ -1,9 +1,9 @@
 use core::fmt;
-use crate::pager::Pager;
 
 use ratatui::widgets::ScrollbarState;
 
-use crate::{btree::BTree, db::list_databases};
+use crate::db::{Db, database_len, list_databases};
+use crate::snapshot::Snapshot;
 
 // Representa a tela atual da aplicação
 pub enum CurrentScreen {
@@ -21,22 +21,33 @@ impl Default for CurrentScreen {
 pub enum MainMenu {
     OptionsList, // Apresenta as opções do menu principal
     CreateDb,    // Pop-up para entrada de usuário com o nome de um novo banco de dados
+    ImportDb,    // Pop-up com o nome de um novo banco de dados e o diretório a importar
     SuccessMessage,
-    // FailureMessage,
+    FailureMessage,
 }
 
 pub enum DatabasePrompt {
     SelectCommand, // Apresenta os comandos disponíveis para executar no banco de dados
     UserInput,     // Pop-up para entrada de usuário com parâmetros do comando selecionado
-    ResultView,    // Tela de resultados da operação de busca (SEARCH)
+    ResultView,    // Tela de resultados das operações de busca (SEARCH, SCAN) e navegação (BROWSE)
     SuccessMessage,
     FailureMessage,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum DatabaseCommands {
     SEARCH,
     INSERT,
     DELETE,
+    SCAN,
+    BROWSE,
+    BEGIN,
+    COMMIT,
+    ROLLBACK,
+    CHECKPOINT,
+    RESTORE,
+    BRANCH,
+    COMPACT,
     CLOSE,
 }
 
@@ -46,6 +57,15 @@ impl fmt::Display for DatabaseCommands {
             Self::SEARCH => write!(f, "SEARCH"),
             Self::INSERT => write!(f, "INSERT"),
             Self::DELETE => write!(f, "DELETE"),
+            Self::SCAN => write!(f, "SCAN"),
+            Self::BROWSE => write!(f, "BROWSE"),
+            Self::BEGIN => write!(f, "BEGIN"),
+            Self::COMMIT => write!(f, "COMMIT"),
+            Self::ROLLBACK => write!(f, "ROLLBACK"),
+            Self::CHECKPOINT => write!(f, "CHECKPOINT"),
+            Self::RESTORE => write!(f, "RESTORE"),
+            Self::BRANCH => write!(f, "BRANCH"),
+            Self::COMPACT => write!(f, "COMPACT"),
             Self::CLOSE => write!(f, "CLOSE"),
         }
     }
@@ -55,14 +75,29 @@ impl fmt::Display for DatabaseCommands {
 // Representa o estado atual da aplicação
 pub struct App {
     pub input: String,
-    pub loaded_db: Option<Pager>,
+    pub loaded_db: Option<Db>,
+    pub loaded_db_name: String,
+    // Quantidade de chaves do banco carregado, exibida na linha de status. É lida de novo só depois
+    // dos comandos que alteram o banco: lê-la a cada quadro contaria como acesso nas estatísticas do cache
+    pub loaded_db_len: Option<u64>,
     pub databases: Vec<String>,
+    // Quantidade de chaves de cada banco da listagem (None se o arquivo não pôde ser lido)
+    pub database_lens: Vec<Option<u64>>,
     pub current_screen: CurrentScreen,
     pub option_highlighted: u8,
     pub db_command: Option<DatabaseCommands>,
-    pub index: BTree,
     pub search_result: String,
-    // pub failure_message: String,
+    // Posição da entrada exibida pelo BROWSE e total de entradas
+    pub browse_position: Option<(u64, u64)>,
+    // Chave exibida pelo BROWSE, que pode não ser texto (a entrada mostra só uma aproximação)
+    pub browse_key: Vec<u8>,
+    // Páginas do SCAN: início de cada página visitada (None na primeira) e da página seguinte
+    pub scan_pages: Vec<Option<Vec<u8>>>,
+    pub scan_next: Option<Vec<u8>>,
+    // Todas as páginas do SCAN são lidas do snapshot tirado na primeira
+    pub scan_snapshot: Option<Snapshot>,
+    pub success_message: String,
+    pub failure_message: String,
     pub vertical_scroll_state: ScrollbarState,
     pub vertical_scroll: u16,
     pub line_count: usize
@@ -76,8 +111,14 @@ impl App {
         app
     }
 
+    // Relê a quantidade de chaves do banco carregado (None se a leitura falhar)
+    pub fn refresh_len(&mut self) {
+        self.loaded_db_len = self.loaded_db.as_mut().and_then(|db| db.len().ok());
+    }
+
     // Atualiza a lista de banco de dados da aplicação
     pub fn fetch_databases(&mut self) {
         self.databases = list_databases();
+        self.database_lens = self.databases.iter().map(|name| database_len(name).ok()).collect();
     }
 }
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+// Alteração de uma chave dentro de um lote
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Delete { key: Vec<u8> },
+}
+
+// Lote de inserções e remoções aplicado como uma unidade (ver `BTree::write`): as operações são
+// aplicadas em ordem e a nova raiz só é publicada depois que todas derem certo. Se uma delas
+// falhar, nenhuma aparece no banco
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        WriteBatch::default()
+    }
+
+    // Aceita bytes ou texto (`String`, `&str`) nas chaves e nos valores
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.ops.push(BatchOp::Put { key: key.into(), value: value.into() });
+        self
+    }
+
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) -> &mut Self {
+        self.ops.push(BatchOp::Delete { key: key.into() });
+        self
+    }
+
+    // Acrescenta as operações de outro lote ao fim deste
+    pub fn append(&mut self, other: WriteBatch) {
+        self.ops.extend(other.ops);
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = BatchOp;
+    type IntoIter = std::vec::IntoIter<BatchOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
@@ -1,24 +1,133 @@
 use serde::{Serialize, Deserialize};
-use std::io::Error;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::ops::RangeBounds;
+use std::rc::Rc;
+use crate::batch::{BatchOp, WriteBatch};
+use crate::cursor::{Cursor, Range};
+use crate::node_page::{self, NodePage};
 use crate::pager::Pager;
 
-// Constantes da B-Tree
-const T: usize = 3; 
-const MAX_KEYS: usize = 2 * T - 1; 
+// Os nós são divididos e fundidos de acordo com o tamanho serializado em relação à página.
+// A ordem gravada no cabeçalho só limita a quantidade de chaves por nó (2 * ordem - 1);
+// a padrão é alta o bastante para que o limite prático seja sempre o tamanho da página.
+pub const DEFAULT_ORDER: usize = 1024;
+
+// Limite de tamanho das chaves. As maiores que `node_page::MAX_INLINE_KEY_SIZE` ficam em cadeias de
+// overflow, regravadas a cada cópia do nó, e as comparações com elas podem precisar ler a cadeia inteira
+pub const MAX_KEY_SIZE: usize = 64 * 1024;
+// Valores maiores que isso são gravados fora do nó, em páginas de overflow
+const MAX_INLINE_VALUE_SIZE: usize = 512;
+
+// Maior acréscimo de bytes que uma única entrada (chave + valor + ponteiro para filho)
+// pode causar em uma página de nó
+const MAX_ENTRY_SIZE: usize = node_page::max_entry_size(node_page::MAX_INLINE_KEY_SIZE, MAX_INLINE_VALUE_SIZE);
+
+fn max_keys(order: usize) -> usize {
+    2 * order - 1
+}
+
+// Valores grandes continuam fora do nó mesmo nas páginas maiores, mas em páginas pequenas
+// o limite garante que uma entrada nunca passe de 1/4 da página: assim a divisão de um nó
+// que estourou (ou a redistribuição entre dois irmãos) sempre gera nós que cabem na página
+fn max_inline_value_size(page_data_size: usize) -> usize {
+    let limit = (page_data_size / 4).saturating_sub(MAX_ENTRY_SIZE - MAX_INLINE_VALUE_SIZE);
+    limit.min(MAX_INLINE_VALUE_SIZE)
+}
+
+// Verifica a ordem de um banco de dados (o tamanho dos nós é controlado pelo tamanho da página)
+pub fn check_order(order: usize) -> Result<(), Error> {
+    if order < 2 || order > u32::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Ordem {order} inválida (mínimo 2)")));
+    }
+    Ok(())
+}
+
+// Organização da árvore, escolhida na criação do banco de dados
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    // B-Tree clássica: cada chave aparece uma única vez, com o seu valor, em qualquer nível
+    #[default]
+    BTree,
+    // B+Tree: os nós internos guardam só chaves separadoras e todos os valores ficam nas
+    // folhas, cada uma ligada à seguinte pelo offset da sua página (ver `Node::next`)
+    BPlusTree,
+}
+
+impl Engine {
+    // Identificador gravado no cabeçalho
+    pub fn id(self) -> u32 {
+        match self {
+            Engine::BTree => 0,
+            Engine::BPlusTree => 1,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Result<Self, Error> {
+        match id {
+            0 => Ok(Engine::BTree),
+            1 => Ok(Engine::BPlusTree),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("Organização de árvore {id} desconhecida"))),
+        }
+    }
+}
+
+impl std::str::FromStr for Engine {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.to_lowercase().as_str() {
+            "btree" => Ok(Engine::BTree),
+            "bplus" => Ok(Engine::BPlusTree),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Organização de árvore desconhecida: {s} (use btree ou bplus)"),
+            )),
+        }
+    }
+}
+
+// Estado publicado no início de uma transação (ver `BTree::begin`)
+#[derive(Debug, Clone, Copy)]
+pub struct Transaction {
+    root: Option<u64>,
+    key_count: u64,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BTree {
     pub root: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Valor armazenado em um nó: diretamente no nó ou em uma cadeia de páginas de overflow
+#[derive(Debug, Clone)]
+pub enum Value {
+    Inline(Vec<u8>),
+    Overflow { offset: u64, length: u64 },
+}
+
+// Nó decodificado, usado pelas operações que alteram a árvore; no arquivo cada nó
+// ocupa uma página no formato descrito em `node_page`. Os nós internos da B+Tree
+// não têm valores (`values` vazio)
+#[derive(Debug, Clone)]
 pub struct Node {
-    pub keys: Vec<String>,
-    pub values: Vec<String>,
-    pub children: Vec<u64>, 
+    pub keys: Vec<Vec<u8>>,
+    pub values: Vec<Value>,
+    pub children: Vec<u64>,
+    // Quantidade de entradas na subárvore de cada filho (mesmo índice de `children`)
+    pub counts: Vec<u64>,
     pub is_leaf: bool,
-    #[serde(skip)]
     pub id: Option<u64>,
+    // Folhas da B+Tree: offset da folha seguinte, ou None na última folha. Como cada escrita grava
+    // a folha alterada em outra página (copy-on-write), a folha da esquerda continua apontando para
+    // a versão antiga; por isso o cursor só segue as ligações quando a raiz tem `linked`
+    pub next: Option<u64>,
+    // Raiz de uma árvore em que todas as ligações entre folhas estão corretas: montada pela carga
+    // em lote e ainda não alterada. Todo nó carregado para ser alterado perde a marca
+    pub linked: bool,
+    // Cadeias de overflow das chaves longas, na ordem das chaves, gravadas junto com a página `id`.
+    // Cada versão do nó tem as suas: elas são liberadas junto com a página e gravadas de novo pela cópia
+    pub key_chains: Vec<u64>,
 }
 
 impl Default for BTree {
@@ -32,417 +141,884 @@ impl BTree {
         BTree { root: root_offset }
     }
 
-    pub fn insert(&mut self, key: String, value: String, pager: &mut Pager) -> Result<(), Error> {
-        let root_offset: u64;
+    pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>, pager: &mut Pager) -> Result<(), Error> {
+        self.publish(pager, |tree, pager| tree.put(key, value, pager))
+    }
 
-        if let Some(root_id) = self.root {
-            let mut root_node = Node::load(root_id, pager)?;
+    pub fn delete(&mut self, key: Vec<u8>, pager: &mut Pager) -> Result<(), Error> {
+        self.publish(pager, |tree, pager| tree.remove(&key, pager))
+    }
 
-            if root_node.keys.len() == MAX_KEYS {
-                let mut new_root = Node::new(false);
-                new_root.children.push(root_id); 
-                
-                new_root.split_child(0, &mut root_node, pager)?;
+    // Aplica as operações do lote em ordem e publica uma única raiz no fim
+    pub fn write(&mut self, batch: WriteBatch, pager: &mut Pager) -> Result<(), Error> {
+        self.publish(pager, |tree, pager| tree.stage(batch, pager))
+    }
 
-                let i = if key > new_root.keys[0] { 1 } else { 0 };
-                
-                let mut child = Node::load(new_root.children[i], pager)?;
-                
-                // CORREÇÃO: Atualiza o ponteiro do filho modificado na nova raiz
-                new_root.children[i] = child.insert_non_full(key, value, pager)?;
+    // Início de uma transação: a raiz e a quantidade de chaves publicadas. As alterações feitas com
+    // `stage` montam uma raiz privada, que só é publicada por `commit`; `rollback` volta à capturada
+    pub fn begin(&self, pager: &Pager) -> Transaction {
+        Transaction { root: self.root, key_count: pager.key_count() }
+    }
 
-                root_offset = new_root.save(pager)?;
-                self.root = Some(root_offset);
-            } else {
-                // CORREÇÃO: A raiz mudou de lugar (append only), atualiza self.root
-                root_offset = root_node.insert_non_full(key, value, pager)?;
-                self.root = Some(root_offset);
-            }
-        } else {
-            let mut root_node = Node::new(true);
-            
-            root_offset = root_node.insert_non_full(key, value, pager)?;
-            self.root = Some(root_offset);
+    // Aplica o lote sem publicar a nova raiz. Se uma operação falhar, só este lote é desfeito
+    pub fn stage(&mut self, batch: WriteBatch, pager: &mut Pager) -> Result<(), Error> {
+        self.change(pager, |tree, pager| {
+            batch.into_iter().try_for_each(|op| match op {
+                BatchOp::Put { key, value } => tree.put(key, value, pager),
+                BatchOp::Delete { key } => tree.remove(&key, pager),
+            })
+        })
+    }
+
+    // Publica a raiz privada da transação de uma só vez
+    pub fn commit(&mut self, transaction: Transaction, pager: &mut Pager) -> Result<(), Error> {
+        if self.root == transaction.root {
+            return Ok(());
         }
+        let result = pager.update_root_offset(&self.root.unwrap_or(0).to_be_bytes());
+        if result.is_err() {
+            self.rollback(transaction, pager);
+        }
+        result
+    }
 
-        pager.update_root_offset(&root_offset.to_be_bytes())
+    // Descarta a raiz privada: as páginas gravadas pela transação nunca foram publicadas e as
+    // liberadas por ela continuam referenciadas pela raiz capturada no início
+    pub fn rollback(&mut self, transaction: Transaction, pager: &mut Pager) {
+        self.root = transaction.root;
+        pager.set_key_count(transaction.key_count);
+        pager.discard_pending();
     }
 
-    pub fn search(&self, key: &str, pager: &mut Pager) -> Option<String> {
-        if let Some(root_id) = self.root {
-            if let Ok(root_node) = Node::load(root_id, pager) {
-                return root_node.search(key, pager);
-            }
-        }
-        None
+    // Executa a alteração e publica a nova raiz (se houver uma), como uma transação de uma operação
+    fn publish<F>(&mut self, pager: &mut Pager, change: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut BTree, &mut Pager) -> Result<(), Error>,
+    {
+        let transaction = self.begin(pager);
+        self.change(pager, change)?;
+        self.commit(transaction, pager)
     }
 
-    pub fn delete(&mut self, key: String, pager: &mut Pager) -> Result<(), Error> {
-        // println!("DEBUG: BTree::delete chamado para chave '{}'", key);
-        if let Some(root_id) = self.root {
-            let mut root_node = Node::load(root_id, pager)?;
+    // Executa a alteração sem publicar; se ela falhar, a árvore volta para a raiz anterior e nada
+    // do que foi liberado pela alteração pode ser reaproveitado
+    fn change<F>(&mut self, pager: &mut Pager, change: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut BTree, &mut Pager) -> Result<(), Error>,
+    {
+        let (root, key_count, mark) = (self.root, pager.key_count(), pager.pending_mark());
+        change(self, pager).inspect_err(|_| {
+            self.root = root;
+            pager.set_key_count(key_count);
+            pager.discard_pending_since(mark);
+        })
+    }
 
-            root_node.remove_key(key, pager)?;
+    // Insere (ou substitui) a chave sem publicar a nova raiz
+    fn put(&mut self, key: Vec<u8>, value: Vec<u8>, pager: &mut Pager) -> Result<(), Error> {
+        if key.len() > MAX_KEY_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("A chave excede o tamanho máximo de {MAX_KEY_SIZE} bytes"),
+            ));
+        }
 
-            if root_node.keys.is_empty() {
-                if !root_node.is_leaf {
-                    let new_root_id = root_node.children[0];
-                    self.root = Some(new_root_id);
-                } else {
-                    self.root = None;
-                }
-            } else {
-                self.root = Some(root_node.save(pager)?);
+        let existing = self.find(&key, pager)?;
+        let value = Value::new(value, pager)?;
+        self.root = self.insert_value(key, value, pager)?;
+
+        match existing {
+            // O valor substituído deixa de ser referenciado
+            Some(Value::Overflow { offset, .. }) => pager.free_overflow(offset),
+            Some(Value::Inline(_)) => Ok(()),
+            None => {
+                pager.set_key_count(pager.key_count() + 1);
+                Ok(())
             }
         }
-        Ok(())
     }
-}
 
-impl Node {
-    pub fn new(is_leaf: bool) -> Self {
-        Node {
-            keys: Vec::new(),
-            values: Vec::new(),
-            children: Vec::new(),
-            is_leaf,
-            id: None,
+    // Insere (ou substitui, se a chave já existir) o valor e retorna o offset da nova raiz, sem publicá-la
+    fn insert_value(&mut self, key: Vec<u8>, value: Value, pager: &mut Pager) -> Result<Option<u64>, Error> {
+        let mut root_node = match self.root {
+            Some(root_id) => Node::load_mut(root_id, pager)?,
+            None => Node::new(true),
+        };
+
+        root_node.insert(key, value, pager)?;
+        Self::save_root(root_node, pager)
+    }
+
+    // Erros de leitura (ex.: página corrompida) são propagados em vez de tratados como ausência
+    pub fn search(&self, key: &[u8], pager: &mut Pager) -> Result<Option<Vec<u8>>, Error> {
+        match self.find(key, pager)? {
+            Some(value) => Ok(Some(value.read(pager)?)),
+            None => Ok(None),
         }
     }
 
-    pub fn load(offset: u64, pager: &mut Pager) -> Result<Self, Error> {
-        let data = pager.read_at(offset, 4096)?;
-        let mut node = Node::from_bytes(&data)?;
-        node.id = Some(offset);
-        Ok(node)
+    // Cursor sobre as entradas da árvore em ordem de chave, posicionado antes da primeira
+    pub fn cursor<'a>(&self, pager: &'a mut Pager) -> Result<Cursor<'a>, Error> {
+        Cursor::new(self.root, pager)
     }
 
-    pub fn save(&self, pager: &mut Pager) -> Result<u64, Error> {
-        let mut data = self.to_bytes()?;
-        data.resize(4096, 0); 
-        
-        let offset = pager.get_end_offset()?; 
-        pager.write_at(offset, &data)?;
-        Ok(offset)
+    // Entradas com chave no intervalo, em ordem; a busca desce direto até o início do intervalo
+    pub fn range<'a, R: RangeBounds<Vec<u8>>>(&self, range: R, pager: &'a mut Pager) -> Result<Range<'a>, Error> {
+        Range::new(range, self.root, pager)
     }
 
-    // CORREÇÃO CRÍTICA: Agora retorna Result<u64, Error> (o novo ID do nó)
-    fn insert_non_full(&mut self, key: String, value: String, pager: &mut Pager) -> Result<u64, Error> {
-        let mut i = self.keys.len();
+    // Entradas cujas chaves começam com `prefix`, em ordem
+    pub fn scan_prefix<'a>(&self, prefix: &[u8], pager: &'a mut Pager) -> Result<Range<'a>, Error> {
+        Range::prefix(prefix, self.root, pager)
+    }
 
-        if self.is_leaf {
-            while i > 0 && key < self.keys[i - 1] {
-                i -= 1;
-            }
-            self.keys.insert(i, key);
-            self.values.insert(i, value);
-            // Retorna o novo ID gerado pelo save
-            return self.save(pager);
-        } else {
-            while i > 0 && key < self.keys[i - 1] {
-                i -= 1;
+    // Localiza a referência ao valor de uma chave, sem ler as páginas de overflow.
+    // A busca binária acontece direto nas páginas em cache: nenhum nó é decodificado
+    fn find(&self, key: &[u8], pager: &mut Pager) -> Result<Option<Value>, Error> {
+        let Some(mut offset) = self.root else {
+            return Ok(None);
+        };
+
+        loop {
+            let (page, found) = node_page::search(pager, offset, key)?;
+            match found {
+                // Separador da B+Tree: a chave, se existir, está na subárvore da direita
+                Ok(i) if !page.has_values() => offset = page.child(i + 1)?,
+                Ok(i) => return Ok(Some(page.value(i)?)),
+                Err(_) if page.is_leaf() => return Ok(None),
+                Err(i) => offset = page.child(i)?,
             }
+        }
+    }
 
-            let child_id = self.children[i];
-            let mut child = Node::load(child_id, pager)?;
+    // Quantidade de entradas da árvore, lida das contagens da raiz
+    pub fn len(&self, pager: &mut Pager) -> Result<u64, Error> {
+        match self.root {
+            Some(offset) => NodePage::new(pager.page(offset)?, offset)?.count(),
+            None => Ok(0),
+        }
+    }
+
+    // Quantidade de chaves menores que `key`, somando as contagens das subárvores à esquerda do caminho
+    pub fn rank(&self, key: &[u8], pager: &mut Pager) -> Result<u64, Error> {
+        let Some(mut offset) = self.root else {
+            return Ok(0);
+        };
 
-            if child.keys.len() == MAX_KEYS {
-                self.split_child(i, &mut child, pager)?;
+        let mut rank = 0;
+        loop {
+            let (page, found) = node_page::search(pager, offset, key)?;
+            if page.is_leaf() {
+                return Ok(rank + found.unwrap_or_else(|i| i) as u64);
+            }
 
-                if key > self.keys[i] {
-                    i += 1;
+            // Filho onde a descida continua; a entrada de um nó interno da B-Tree encerra a busca
+            let i = match found {
+                Ok(i) if page.has_values() => {
+                    for j in 0..=i {
+                        rank += page.child_count(j)?;
+                    }
+                    return Ok(rank + i as u64);
                 }
-                let mut correct_child = Node::load(self.children[i], pager)?;
-                
-                // CORREÇÃO: O pai atualiza o ponteiro para o filho que mudou de lugar!
-                self.children[i] = correct_child.insert_non_full(key, value, pager)?;
-            } else {
-                // CORREÇÃO: O pai atualiza o ponteiro para o filho que mudou de lugar!
-                self.children[i] = child.insert_non_full(key, value, pager)?;
+                Ok(i) => i + 1,
+                Err(i) => i,
+            };
+            for j in 0..i {
+                rank += page.child_count(j)?;
+            }
+            if page.has_values() {
+                rank += i as u64;
             }
+            offset = page.child(i)?;
         }
-        // Salva o pai (que agora tem ponteiros atualizados) e retorna seu novo ID
-        self.save(pager)
     }
 
-    fn split_child(&mut self, i: usize, child: &mut Node, pager: &mut Pager) -> Result<(), Error> {
-        let mut right_node = Node::new(child.is_leaf);
-
-        let _split_idx = T; 
-        
-        let median_key = child.keys[T - 1].clone();
-        let median_val = child.values[T - 1].clone();
+    // Chave na posição `k` (a partir de 0) da ordem crescente, ou None se `k >= len()`
+    pub fn select(&self, mut k: u64, pager: &mut Pager) -> Result<Option<Vec<u8>>, Error> {
+        let Some(mut offset) = self.root else {
+            return Ok(None);
+        };
 
-        right_node.keys = child.keys.drain(T..).collect();
-        right_node.values = child.values.drain(T..).collect();
+        'descend: loop {
+            let page = NodePage::new(pager.page(offset)?, offset)?;
+            if page.is_leaf() {
+                if k >= page.key_count() as u64 {
+                    return Ok(None);
+                }
+                let key = page.key(k as usize)?;
+                return Ok(Some(key.read(pager)?));
+            }
 
-        if !child.is_leaf {
-            right_node.children = child.children.drain(T..).collect();
+            for i in 0..=page.key_count() {
+                let count = page.child_count(i)?;
+                if k < count {
+                    offset = page.child(i)?;
+                    continue 'descend;
+                }
+                k -= count;
+
+                if page.has_values() && i < page.key_count() {
+                    if k == 0 {
+                        let key = page.key(i)?;
+                        return Ok(Some(key.read(pager)?));
+                    }
+                    k -= 1;
+                }
+            }
+            return Ok(None);
         }
+    }
 
-        child.keys.pop(); 
-        child.values.pop();
-
-        let new_left_id = child.save(pager)?; 
-        let right_id = right_node.save(pager)?; 
-
-        self.keys.insert(i, median_key);
-        self.values.insert(i, median_val);
+    // Remove a chave (se existir) sem publicar a nova raiz
+    fn remove(&mut self, key: &[u8], pager: &mut Pager) -> Result<(), Error> {
+        let Some(root_id) = self.root else {
+            return Ok(());
+        };
 
-        self.children.insert(i + 1, right_id);
-        
-        // CORREÇÃO: Atualiza o ponteiro do filho da esquerda também
-        self.children[i] = new_left_id;
-        
-        self.save(pager)?;
+        let mut root_node = Node::load_mut(root_id, pager)?;
+        // Chave inexistente: nenhum nó foi alterado
+        let Some(removed) = root_node.remove(key, pager)? else {
+            return Ok(());
+        };
+        self.root = Self::save_root(root_node, pager)?;
 
+        // A cadeia de overflow do valor removido deixa de ser referenciada
+        if let Value::Overflow { offset, .. } = removed {
+            pager.free_overflow(offset)?;
+        }
+        pager.set_key_count(pager.key_count() - 1);
         Ok(())
     }
 
-    pub fn search(&self, key: &str, pager: &mut Pager) -> Option<String> {
-        let mut i = 0;
-        while i < self.keys.len() && key > &self.keys[i] {
-            i += 1;
-        }
-        if i < self.keys.len() && key == &self.keys[i] {
-            return Some(self.values[i].clone());
+    // Monta a árvore de baixo para cima em um banco vazio, a partir de entradas em ordem
+    // estritamente crescente de chave. As folhas e os nós internos saem cheios e cada página é
+    // gravada uma única vez (só o último nó de cada nível pode ser redistribuído com o vizinho);
+    // a raiz é publicada uma única vez, no fim
+    pub fn bulk_load<I>(entries: I, pager: &mut Pager) -> Result<BTree, Error>
+    where
+        I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    {
+        if pager.root_offset().is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "A carga em lote exige um banco de dados vazio"));
         }
-        if self.is_leaf {
-            return None;
-        }
-        if let Ok(child_node) = Node::load(self.children[i], pager) {
-            return child_node.search(key, pager);
+
+        let mut loader = BulkLoader::new(pager);
+        let root = entries
+            .into_iter()
+            .try_for_each(|(key, value)| loader.push(key, value))
+            .and_then(|_| loader.finish());
+        let count = loader.count;
+
+        match root {
+            Ok(root) => {
+                pager.set_key_count(count);
+                pager.update_root_offset(&root.unwrap_or(0).to_be_bytes())?;
+                Ok(BTree::new(root))
+            }
+            Err(e) => {
+                pager.discard_pending();
+                Err(e)
+            }
         }
-        None
     }
 
-    fn remove_key(&mut self, key: String, pager: &mut Pager) -> Result<(), Error> {
-        // println!("DEBUG: remove_key visitando nó (Leaf={}). Chaves: {:?}", self.is_leaf, self.keys);
+    // Todas as páginas alcançáveis a partir da raiz: nós e cadeias de overflow das chaves e dos valores
+    pub fn pages(&self, pager: &mut Pager) -> Result<Vec<u64>, Error> {
+        let mut pages = Vec::new();
+        let mut stack: Vec<u64> = self.root.into_iter().collect();
+        while let Some(offset) = stack.pop() {
+            let node = Node::load(offset, pager)?;
+            pages.push(offset);
+            for &chain in &node.key_chains {
+                pages.extend(pager.overflow_pages(chain)?);
+            }
+            for value in &node.values {
+                if let Value::Overflow { offset, .. } = value {
+                    pages.extend(pager.overflow_pages(*offset)?);
+                }
+            }
+            stack.extend(&node.children);
+        }
+        Ok(pages)
+    }
 
-        let idx_search = self.keys.binary_search(&key);
+    // Copia apenas os nós alcançáveis a partir da raiz para outro arquivo e retorna a raiz da cópia.
+    // `copied` liga cada página já copiada à sua cópia: árvores que compartilham páginas (a atual e
+    // as dos checkpoints) continuam compartilhando no destino
+    pub fn copy_to(&self, pager: &mut Pager, target: &mut Pager, copied: &mut HashMap<u64, u64>) -> Result<Option<u64>, Error> {
+        match self.root {
+            Some(root_id) => Ok(Some(Node::copy_to(root_id, pager, target, copied)?)),
+            None => Ok(None),
+        }
+    }
 
-        if let Ok(idx) = idx_search {
-            // println!("DEBUG: Chave '{}' encontrada neste nó no índice {}.", key, idx);
-            if self.is_leaf {
-                self.keys.remove(idx);
-                self.values.remove(idx);
-                self.save(pager)?;
-            } else {
-                self.delete_internal_node(idx, pager)?;
-            }
-            return Ok(());
+    // Grava a raiz modificada e retorna o seu offset, sem publicá-lo. Uma raiz que não cabe
+    // mais na página é dividida sob uma nova raiz; uma raiz sem chaves é descartada
+    fn save_root(mut root_node: Node, pager: &mut Pager) -> Result<Option<u64>, Error> {
+        if root_node.is_overfull(pager) {
+            let mut new_root = Node::new(false);
+            new_root.linked = std::mem::take(&mut root_node.linked);
+            new_root.children.push(0);
+            new_root.counts.push(0);
+            new_root.split_child(0, root_node, pager)?;
+            return Ok(Some(new_root.save(pager)?));
         }
 
-        let idx = idx_search.unwrap_err();
-        
-        if self.is_leaf {
-            // println!("DEBUG: Nó é folha e chave não encontrada. Fim da linha.");
-            return Ok(());
+        if root_node.keys.is_empty() {
+            root_node.free(pager)?;
+            return Ok(if root_node.is_leaf { None } else { Some(root_node.children[0]) });
         }
 
-        let child_id = self.children[idx];
-        let child_node = Node::load(child_id, pager)?;
+        Ok(Some(root_node.save(pager)?))
+    }
+}
+
+// Estado da carga em lote: o nó em construção de cada nível (0 = folhas) com o seu tamanho
+// serializado. Um nó cheio é gravado e vira filho do nó em construção do nível de cima
+struct BulkLoader<'a> {
+    pager: &'a mut Pager,
+    levels: Vec<(Node, usize)>,
+    // B+Tree: a última folha completa, ainda não gravada, com a página reservada para ela. Ela só
+    // é gravada quando a folha seguinte ganha a sua página, para já nascer apontando para ela
+    held: Option<(Node, u64)>,
+    last_key: Option<Vec<u8>>,
+    count: u64,
+}
 
-        if child_node.keys.len() < T {
-            // println!("DEBUG: Filho {} tem poucas chaves. Executando fill.", idx);
-            self.fill(idx, pager)?;
+impl<'a> BulkLoader<'a> {
+    fn new(pager: &'a mut Pager) -> Self {
+        let leaf = Node::new(true);
+        let size = node_page::encoded_size(&leaf);
+        BulkLoader { pager, levels: vec![(leaf, size)], held: None, last_key: None, count: 0 }
+    }
+
+    fn push(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), Error> {
+        if key.len() > MAX_KEY_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("A chave excede o tamanho máximo de {MAX_KEY_SIZE} bytes"),
+            ));
+        }
+        if self.last_key.as_ref().is_some_and(|last| *last >= key) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Entradas fora de ordem ou repetidas na carga em lote: {}", String::from_utf8_lossy(&key)),
+            ));
         }
 
-        let child_idx = match self.keys.binary_search(&key) {
-            Ok(_) => unreachable!("Erro de Lógica: Chave apareceu no pai durante a descida"),
-            Err(i) => i, 
-        };
-        // println!("DEBUG: Descendo para filho índice {} após verificação de fill.", child_idx);
+        self.last_key = Some(key.clone());
+        self.count += 1;
+        let value = Value::new(value, self.pager)?;
+        self.push_leaf(key, value)
+    }
 
-        let child_id_final = self.children[child_idx];
-        let mut child_final = Node::load(child_id_final, pager)?;
-        
-        child_final.remove_key(key, pager)?;
+    fn is_full(&self, level: usize, entry_size: usize) -> bool {
+        let (node, size) = &self.levels[level];
+        node.keys.len() >= max_keys(self.pager.order()) || size + entry_size > self.pager.page_data_size()
+    }
 
-        // CORREÇÃO DE DELETE: Atualiza ponteiro do filho modificado
-        self.children[child_idx] = child_final.save(pager)?;
-        self.save(pager)?;
+    fn push_leaf(&mut self, key: Vec<u8>, value: Value) -> Result<(), Error> {
+        let entry_size = node_page::entry_size(&key, Some(&value), true);
 
+        if self.pager.engine() == Engine::BTree {
+            // Na B-Tree a entrada que não cabe mais na folha sobe para o pai, com o seu valor
+            if self.is_full(0, entry_size) {
+                self.finish_node(0)?;
+                return self.push_separator(1, key, Some(value));
+            }
+        } else if self.is_full(0, entry_size) {
+            self.hold_leaf()?;
+        }
+
+        let (leaf, size) = &mut self.levels[0];
+        leaf.keys.push(key);
+        leaf.values.push(value);
+        *size += entry_size;
         Ok(())
     }
 
-    fn delete_internal_node(&mut self, idx: usize, pager: &mut Pager) -> Result<(), Error> {
-        let key_to_delete = self.keys[idx].clone();
+    fn push_separator(&mut self, level: usize, key: Vec<u8>, value: Option<Value>) -> Result<(), Error> {
+        let entry_size = node_page::entry_size(&key, value.as_ref(), false);
+        if self.is_full(level, entry_size) {
+            self.finish_node(level)?;
+            return self.push_separator(level + 1, key, value);
+        }
 
-        let left_child_id = self.children[idx];
-        let mut left_child = Node::load(left_child_id, pager)?;
+        let (node, size) = &mut self.levels[level];
+        node.keys.push(key);
+        node.values.extend(value);
+        *size += entry_size;
+        Ok(())
+    }
 
-        if left_child.keys.len() >= T {
-            let (pred_key, pred_val) = left_child.get_predecessor(pager)?;
-            
-            self.keys[idx] = pred_key.clone();
-            self.values[idx] = pred_val;
-            
-            left_child.remove_key(pred_key, pager)?;
-            
-            self.children[idx] = left_child.save(pager)?;
-            self.save(pager)?; 
+    // Tira do nível o nó em construção, deixando um vazio no lugar
+    fn take_node(&mut self, level: usize) -> Node {
+        let empty = Node::new(level == 0);
+        let empty_size = node_page::encoded_size(&empty);
+        std::mem::replace(&mut self.levels[level], (empty, empty_size)).0
+    }
 
-        } else {
-            let right_child_id = self.children[idx + 1];
-            let mut right_child = Node::load(right_child_id, pager)?;
-
-            if right_child.keys.len() >= T {
-                let (succ_key, succ_val) = right_child.get_successor(pager)?;
-                
-                self.keys[idx] = succ_key.clone();
-                self.values[idx] = succ_val;
-                
-                right_child.remove_key(succ_key, pager)?;
-                
-                self.children[idx + 1] = right_child.save(pager)?;
-                self.save(pager)?;
+    // Grava o nó em construção do nível e o acrescenta como filho do nível de cima
+    fn finish_node(&mut self, level: usize) -> Result<(), Error> {
+        let node = self.take_node(level);
+        let count = node.count();
+        let offset = node.save(self.pager)?;
+        self.push_child(level + 1, offset, count);
+        Ok(())
+    }
 
-            } else {
-                self.merge(idx, pager)?;
-                
-                let merged_child_id = self.children[idx];
-                let mut merged_child = Node::load(merged_child_id, pager)?;
-                
-                merged_child.remove_key(key_to_delete, pager)?;
-                
-                self.children[idx] = merged_child.save(pager)?;
-                self.save(pager)?;
-            }
+    fn push_child(&mut self, level: usize, offset: u64, count: u64) {
+        if self.levels.len() == level {
+            let parent = Node::new(false);
+            let size = node_page::encoded_size(&parent);
+            self.levels.push((parent, size));
+        }
+        let (parent, _) = &mut self.levels[level];
+        parent.children.push(offset);
+        parent.counts.push(count);
+    }
+
+    // B+Tree: reserva a página da folha que acabou de encher e grava a anterior apontando para ela.
+    // A primeira chave da folha cheia é copiada para o pai como separador
+    fn hold_leaf(&mut self) -> Result<(), Error> {
+        let leaf = self.take_node(0);
+        let separator = leaf.keys[0].clone();
+        let offset = self.pager.reserve()?;
+        if let Some((held, held_offset)) = self.held.replace((leaf, offset)) {
+            self.write_leaf(held, held_offset, Some(offset))?;
+            self.push_separator(1, separator, None)?;
         }
         Ok(())
     }
 
-    fn fill(&mut self, idx: usize, pager: &mut Pager) -> Result<(), Error> {
-        if idx > 0 {
-            let left_sib_id = self.children[idx - 1];
-            let left_sib = Node::load(left_sib_id, pager)?;
-            if left_sib.keys.len() >= T {
-                self.borrow_from_prev(idx, pager)?;
-                return Ok(());
+    // Grava a folha na página reservada para ela e a acrescenta como filha do nível de cima
+    fn write_leaf(&mut self, mut leaf: Node, offset: u64, next: Option<u64>) -> Result<(), Error> {
+        leaf.next = next;
+        let count = leaf.count();
+        leaf.save_at(Some(offset), self.pager)?;
+        self.push_child(1, offset, count);
+        Ok(())
+    }
+
+    // B+Tree: grava as duas últimas folhas. A última pode ter ficado pequena demais; ela é fundida
+    // (ou redistribuída) com a anterior ainda em memória, então nenhuma folha gravada muda de página
+    // e todas as ligações continuam corretas
+    fn finish_leaves(&mut self, mut held: Node, offset: u64) -> Result<(), Error> {
+        let mut last = self.take_node(0);
+        if last.is_underfull(self.pager) {
+            held.keys.append(&mut last.keys);
+            held.values.append(&mut last.values);
+            if !held.is_overfull(self.pager) {
+                return self.write_leaf(held, offset, None);
             }
+            let mid = held.split_point(self.pager)?;
+            last.keys = held.keys.split_off(mid);
+            last.values = held.values.split_off(mid);
         }
-        if idx < self.children.len() - 1 {
-            let right_sib_id = self.children[idx + 1];
-            let right_sib = Node::load(right_sib_id, pager)?;
-            if right_sib.keys.len() >= T {
-                self.borrow_from_next(idx, pager)?;
-                return Ok(());
-            }
+
+        let separator = last.keys[0].clone();
+        let last_offset = self.pager.reserve()?;
+        self.write_leaf(held, offset, Some(last_offset))?;
+        self.push_separator(1, separator, None)?;
+        self.write_leaf(last, last_offset, None)
+    }
+
+    // Fecha os nós em construção de baixo para cima e retorna a raiz. O último nó de cada nível
+    // pode ter ficado pequeno demais: `update_child` o funde (ou redistribui) com o vizinho
+    fn finish(&mut self) -> Result<Option<u64>, Error> {
+        if self.count == 0 {
+            return Ok(None);
         }
-        if idx < self.children.len() - 1 {
-            self.merge(idx, pager)?;
-        } else {
-            self.merge(idx - 1, pager)?;
+
+        // Com mais de uma folha na B+Tree, todas elas já são filhas do nível de cima
+        let leaves = match self.held.take() {
+            Some((held, offset)) => {
+                self.finish_leaves(held, offset)?;
+                1
+            }
+            None => 0,
+        };
+
+        let mut levels = std::mem::take(&mut self.levels).into_iter().map(|(node, _)| node).skip(leaves);
+        let mut node = levels.next().unwrap();
+        for mut parent in levels {
+            parent.children.push(0);
+            parent.counts.push(0);
+            parent.update_child(parent.children.len() - 1, node, self.pager)?;
+            node = parent;
         }
-        Ok(())
+        // Na árvore recém-montada todas as folhas apontam para a seguinte
+        node.linked = self.pager.engine() == Engine::BPlusTree;
+        BTree::save_root(node, self.pager)
     }
+}
 
-    fn borrow_from_prev(&mut self, idx: usize, pager: &mut Pager) -> Result<(), Error> {
-        let child_id = self.children[idx];
-        let sibling_id = self.children[idx - 1];
+impl Value {
+    // Valores grandes vão para páginas de overflow; o nó guarda só o offset e o tamanho
+    pub fn new(value: Vec<u8>, pager: &mut Pager) -> Result<Self, Error> {
+        if value.len() <= max_inline_value_size(pager.page_data_size()) {
+            return Ok(Value::Inline(value));
+        }
 
-        let mut child = Node::load(child_id, pager)?;
-        let mut sibling = Node::load(sibling_id, pager)?;
+        let offset = pager.write_overflow(&value)?;
+        Ok(Value::Overflow { offset, length: value.len() as u64 })
+    }
 
-        child.keys.insert(0, self.keys[idx-1].clone());
-        child.values.insert(0, self.values[idx-1].clone());
+    // Recupera o valor completo, remontando a cadeia de overflow se necessário
+    pub fn read(&self, pager: &mut Pager) -> Result<Vec<u8>, Error> {
+        match self {
+            Value::Inline(value) => Ok(value.clone()),
+            Value::Overflow { offset, length } => pager.read_overflow(*offset, *length as usize),
+        }
+    }
+}
 
-        self.keys[idx-1]= sibling.keys.pop().unwrap();
-        self.values[idx-1] = sibling.values.pop().unwrap();
+impl Node {
+    pub fn new(is_leaf: bool) -> Self {
+        Node {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+            counts: Vec::new(),
+            is_leaf,
+            id: None,
+            next: None,
+            linked: false,
+            key_chains: Vec::new(),
+        }
+    }
 
-        if !child.is_leaf {
-            let sib_child_ptr = sibling.children.pop().unwrap();
-            child.children.insert(0, sib_child_ptr);
+    // Nó somente para leitura, compartilhado com o cache
+    pub fn load(offset: u64, pager: &mut Pager) -> Result<Rc<Self>, Error> {
+        // Nós já decodificados não passam de novo pelo bincode
+        if let Some(node) = pager.cached_node(offset) {
+            return Ok(node);
         }
 
-        self.children[idx] = child.save(pager)?;
-        self.children[idx - 1] = sibling.save(pager)?;
-        self.save(pager)?;
+        let node = Rc::new(Self::decode(offset, pager)?);
+        pager.cache_node(offset, Rc::clone(&node));
+        Ok(node)
+    }
 
-        Ok(())
+    // Nó que vai ser alterado: a versão do cache é copiada, pois continua válida para quem
+    // lê a página antiga. Um nó fora do cache não é guardado nele, já que `save` guarda a nova versão
+    pub fn load_mut(offset: u64, pager: &mut Pager) -> Result<Self, Error> {
+        let mut node = match pager.cached_node(offset) {
+            Some(node) => Rc::unwrap_or_clone(node),
+            None => Self::decode(offset, pager)?,
+        };
+        node.linked = false;
+        Ok(node)
     }
 
-    fn borrow_from_next(&mut self, idx: usize, pager: &mut Pager) -> Result<(), Error> {
-        let child_id = self.children[idx];
-        let sibling_id = self.children[idx + 1];
+    fn decode(offset: u64, pager: &mut Pager) -> Result<Self, Error> {
+        // Checksum válido mas conteúdo inconsistente: a página não contém um nó
+        let (mut node, long_keys) = {
+            let page = NodePage::new(pager.page(offset)?, offset)?;
+            (page.to_node()?, page.long_keys()?)
+        };
+        for (i, chain, length) in long_keys {
+            node.keys[i] = pager.read_overflow(chain, length as usize)?;
+            node.key_chains.push(chain);
+        }
+        node.id = Some(offset);
+        Ok(node)
+    }
 
-        let mut child = Node::load(child_id, pager)?;
-        let mut sibling = Node::load(sibling_id, pager)?;
+    // Grava o nó em uma nova página (copy-on-write); a versão anterior é liberada
+    pub fn save(self, pager: &mut Pager) -> Result<u64, Error> {
+        self.save_at(None, pager)
+    }
 
-        child.keys.push(self.keys[idx].clone());
-        child.values.push(self.values[idx].clone());
+    // Grava o nó na página `offset`, já reservada (ex.: para que a folha anterior pudesse apontar
+    // para ela), ou em uma nova. As chaves longas ganham cadeias novas, e as da versão anterior
+    // são liberadas junto com a sua página
+    fn save_at(mut self, offset: Option<u64>, pager: &mut Pager) -> Result<u64, Error> {
+        let key_chains = self
+            .keys
+            .iter()
+            .filter(|key| node_page::is_long_key(key))
+            .map(|key| pager.write_overflow(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Só depois das cadeias: uma página alocada no fim do arquivo só passa a fazer parte dele
+        // quando é gravada, e as cadeias receberiam o mesmo offset
+        let offset = match offset {
+            Some(offset) => offset,
+            None => pager.allocate()?,
+        };
+        let old_chains = std::mem::replace(&mut self.key_chains, key_chains);
+        let data = node_page::encode(&self, pager.page_data_size())?;
+        pager.write_page(offset, &data)?;
 
-        self.keys[idx] = sibling.keys.remove(0);
-        self.values[idx] = sibling.values.remove(0);
-        
-        if !child.is_leaf {
-            let sib_child_ptr = sibling.children.remove(0);
-            child.children.push(sib_child_ptr);
+        if let Some(old_offset) = self.id.replace(offset) {
+            pager.free(old_offset);
         }
-
-        self.children[idx] = child.save(pager)?;
-        self.children[idx + 1] = sibling.save(pager)?;
-        self.save(pager)?;
-        Ok(())
+        for chain in old_chains {
+            pager.free_overflow(chain)?;
+        }
+        // O nó salvo costuma ser recarregado logo em seguida (ex.: pela próxima operação no mesmo caminho)
+        pager.cache_node(offset, Rc::new(self));
+        Ok(offset)
     }
 
-    fn merge(&mut self, idx: usize, pager: &mut Pager) -> Result<(), Error> {
-        // println!("DEBUG: Realizando Merge no índice {}", idx);
-        let left_child_id = self.children[idx];
-        let right_child_id = self.children[idx + 1];
+    // Copia a subárvore (incluindo cadeias de overflow) para outro arquivo, filhos antes dos pais
+    fn copy_to(offset: u64, pager: &mut Pager, target: &mut Pager, copied: &mut HashMap<u64, u64>) -> Result<u64, Error> {
+        if let Some(&copy) = copied.get(&offset) {
+            return Ok(copy);
+        }
 
-        let mut left_child = Node::load(left_child_id, pager)?;
-        let right_child = Node::load(right_child_id, pager)?; 
+        let mut node = Node::load_mut(offset, pager)?;
+        for i in 0..node.children.len() {
+            node.children[i] = Node::copy_to(node.children[i], pager, target, copied)?;
+        }
 
-        let median_key = self.keys.remove(idx);
-        let median_val = self.values.remove(idx);
-        
-        left_child.keys.push(median_key);
-        left_child.values.push(median_val);
+        for value in node.values.iter_mut() {
+            if let Value::Overflow { offset, length } = value {
+                let copy = match copied.get(offset) {
+                    Some(&copy) => copy,
+                    None => {
+                        let data = pager.read_overflow(*offset, *length as usize)?;
+                        target.write_overflow(&data)?
+                    }
+                };
+                copied.insert(*offset, copy);
+                *offset = copy;
+            }
+        }
 
-        left_child.keys.extend(right_child.keys);
-        left_child.values.extend(right_child.values);
+        // A cópia é um nó novo no arquivo de destino: não há versão anterior a liberar, e as chaves
+        // longas ganham cadeias novas nele. As ligações entre folhas apontam para páginas do arquivo
+        // de origem e não são copiadas
+        node.id = None;
+        node.key_chains.clear();
+        node.next = None;
+        let copy = node.save(target)?;
+        copied.insert(offset, copy);
+        Ok(copy)
+    }
 
-        if !left_child.is_leaf{
-            left_child.children.extend(right_child.children);
+    // Libera a página do nó e as cadeias das suas chaves longas
+    fn free(&self, pager: &mut Pager) -> Result<(), Error> {
+        if let Some(id) = self.id {
+            pager.free(id);
         }
+        self.key_chains.iter().try_for_each(|&chain| pager.free_overflow(chain))
+    }
 
-        self.children.remove(idx + 1);
+    // Quantidade de entradas na subárvore (os separadores da B+Tree não contam)
+    fn count(&self) -> u64 {
+        self.values.len() as u64 + self.counts.iter().sum::<u64>()
+    }
+
+    // Nó interno da B+Tree: só chaves separadoras, sem valores
+    fn has_separators_only(&self, pager: &Pager) -> bool {
+        !self.is_leaf && pager.engine() == Engine::BPlusTree
+    }
+
+    // O nó não cabe mais em uma página (ou passou do limite de chaves da ordem) e precisa ser dividido
+    fn is_overfull(&self, pager: &Pager) -> bool {
+        self.keys.len() > max_keys(pager.order()) || node_page::encoded_size(self) > pager.page_data_size()
+    }
 
-        self.children[idx] = left_child.save(pager)?;
-        self.save(pager)?;
+    // O nó ocupa menos de 1/4 da página (e tem menos chaves que a ordem pede) e deve ser
+    // fundido com um irmão; um nó sem chaves é sempre insuficiente
+    fn is_underfull(&self, pager: &Pager) -> bool {
+        self.keys.is_empty()
+            || (self.keys.len() < pager.order() - 1 && node_page::encoded_size(self) < pager.page_data_size() / 4)
+    }
+
+    // Insere (ou substitui) a entrada na subárvore. O nó fica alterado apenas em memória:
+    // quem o chamou decide se ele precisa ser dividido antes de ser gravado
+    fn insert(&mut self, key: Vec<u8>, value: Value, pager: &mut Pager) -> Result<(), Error> {
+        let separators = self.has_separators_only(pager);
+        match self.keys.binary_search(&key) {
+            Ok(i) if !separators => self.values[i] = value,
+            Err(i) if self.is_leaf => {
+                self.keys.insert(i, key);
+                self.values.insert(i, value);
+            }
+            // Nos nós de separadores a chave igual ao separador fica na subárvore da direita
+            found => {
+                let i = found.map_or_else(|i| i, |i| i + 1);
+                let mut child = Node::load_mut(self.children[i], pager)?;
+                child.insert(key, value, pager)?;
+                self.update_child(i, child, pager)?;
+            }
+        }
         Ok(())
     }
 
-    fn get_predecessor(&self, pager: &mut Pager) -> Result<(String, String), Error> {
+    // Remove a chave da subárvore e retorna o valor removido (None se a chave não existir,
+    // caso em que nada foi alterado). Assim como em `insert`, o nó fica alterado só em memória
+    fn remove(&mut self, key: &[u8], pager: &mut Pager) -> Result<Option<Value>, Error> {
+        let separators = self.has_separators_only(pager);
+        match self.keys.binary_search_by(|k| k.as_slice().cmp(key)) {
+            Ok(i) if self.is_leaf => {
+                self.keys.remove(i);
+                Ok(Some(self.values.remove(i)))
+            }
+            Ok(i) if !separators => {
+                // A entrada é substituída pela predecessora, retirada da folha mais à direita da subárvore da esquerda
+                let mut child = Node::load_mut(self.children[i], pager)?;
+                let (pred_key, pred_value) = child.remove_last(pager)?;
+                self.keys[i] = pred_key;
+                let removed = std::mem::replace(&mut self.values[i], pred_value);
+                self.update_child(i, child, pager)?;
+                Ok(Some(removed))
+            }
+            Err(_) if self.is_leaf => Ok(None),
+            // Na B+Tree o separador igual à chave fica no nó mesmo depois da remoção: ele continua
+            // separando corretamente as duas subárvores
+            found => {
+                let i = found.map_or_else(|i| i, |i| i + 1);
+                let mut child = Node::load_mut(self.children[i], pager)?;
+                let removed = child.remove(key, pager)?;
+                if removed.is_some() {
+                    self.update_child(i, child, pager)?;
+                }
+                Ok(removed)
+            }
+        }
+    }
+
+    // Remove e retorna a maior entrada da subárvore
+    fn remove_last(&mut self, pager: &mut Pager) -> Result<(Vec<u8>, Value), Error> {
         if self.is_leaf {
-            Ok((self.keys.last().unwrap().clone(), self.values.last().unwrap().clone()))
+            return Ok((self.keys.pop().unwrap(), self.values.pop().unwrap()));
+        }
+
+        let i = self.children.len() - 1;
+        let mut child = Node::load_mut(self.children[i], pager)?;
+        let entry = child.remove_last(pager)?;
+        self.update_child(i, child, pager)?;
+        Ok(entry)
+    }
 
+    // Grava o filho alterado no índice `i`: se ele não cabe mais na página é dividido,
+    // se ficou pequeno demais é fundido (ou redistribuído) com um irmão
+    fn update_child(&mut self, i: usize, child: Node, pager: &mut Pager) -> Result<(), Error> {
+        if child.is_overfull(pager) {
+            self.split_child(i, child, pager)
+        } else if child.is_underfull(pager) && self.children.len() > 1 {
+            self.merge_child(i, child, pager)
         } else {
-            let last_child_id = *self.children.last().unwrap();
-            let child = Node::load(last_child_id, pager)?;
-            child.get_predecessor(pager)
+            self.counts[i] = child.count();
+            self.children[i] = child.save(pager)?;
+            Ok(())
         }
     }
 
-    fn get_successor(&self, pager: &mut Pager) -> Result<(String, String), Error> {
-        if self.is_leaf {
-            Ok((self.keys.first().unwrap().clone(), self.values.first().unwrap().clone()))
+    // Divide o filho no índice `i` em dois, promovendo a entrada do meio para este nó.
+    // Na B+Tree a divisão de uma folha apenas copia a primeira chave da metade da direita
+    // para este nó, como separador, e a folha da esquerda passa a apontar para a nova folha.
+    // O ponto de divisão equilibra os bytes das duas metades
+    fn split_child(&mut self, i: usize, mut child: Node, pager: &mut Pager) -> Result<(), Error> {
+        let mid = child.split_point(pager)?;
+        let separators = self.has_separators_only(pager);
+
+        let mut right_node = Node::new(child.is_leaf);
+        let median_key = if child.is_leaf && separators {
+            right_node.keys = child.keys.split_off(mid);
+            right_node.values = child.values.split_off(mid);
+            right_node.next = child.next;
+            right_node.keys[0].clone()
         } else {
-            let first_child_id = self.children[0];
-            let child = Node::load(first_child_id, pager)?;
-            child.get_successor(pager)
+            right_node.keys = child.keys.split_off(mid + 1);
+            if !child.has_separators_only(pager) {
+                right_node.values = child.values.split_off(mid + 1);
+            }
+            if !child.is_leaf {
+                right_node.children = child.children.split_off(mid + 1);
+                right_node.counts = child.counts.split_off(mid + 1);
+            }
+            child.keys.pop().unwrap()
+        };
+
+        if !separators {
+            let median_val = child.values.pop().unwrap();
+            self.values.insert(i, median_val);
         }
+
+        // A folha da direita é gravada antes para que a da esquerda já conheça o seu offset
+        let right_count = right_node.count();
+        let right_id = right_node.save(pager)?;
+        if child.is_leaf && separators {
+            child.next = Some(right_id);
+        }
+        self.counts[i] = child.count();
+        self.children[i] = child.save(pager)?;
+
+        self.keys.insert(i, median_key);
+        self.children.insert(i + 1, right_id);
+        self.counts.insert(i + 1, right_count);
+        Ok(())
     }
 
-    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
-        Ok(bincode::serialize(self).unwrap())
+    // Índice da divisão: minimiza a maior das duas metades (em bytes), respeitando o limite de
+    // chaves da ordem e deixando ao menos uma chave em cada lado. Quando a chave do meio sobe
+    // para o pai, ela não fica em nenhuma das metades; nas folhas da B+Tree ela é só copiada
+    // e abre a metade da direita
+    fn split_point(&self, pager: &Pager) -> Result<usize, Error> {
+        let n = self.keys.len();
+        let promoted = if self.is_leaf && pager.engine() == Engine::BPlusTree { 0 } else { 1 };
+        if n < 2 + promoted {
+            return Err(Error::new(ErrorKind::InvalidData, "Nó pequeno demais para ser dividido"));
+        }
+
+        let mut prefix = vec![0; n + 1];
+        for j in 0..n {
+            prefix[j + 1] = prefix[j] + node_page::entry_size(&self.keys[j], self.values.get(j), self.is_leaf);
+        }
+
+        let limit = max_keys(pager.order());
+        let low = 1.max((n - promoted).saturating_sub(limit));
+        let high = (n - 1 - promoted).min(limit);
+        (low..=high)
+            .min_by_key(|&mid| prefix[mid].max(prefix[n] - prefix[mid + promoted]))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Limite de chaves impede a divisão do nó"))
     }
 
-    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
-        Ok(bincode::deserialize(data).unwrap())
+    // Funde o filho no índice `i` com um irmão vizinho (e a chave separadora deste nó).
+    // Se o resultado não couber em uma página, ele é dividido de novo, o que redistribui
+    // as entradas entre os dois irmãos
+    fn merge_child(&mut self, i: usize, child: Node, pager: &mut Pager) -> Result<(), Error> {
+        let (idx, mut left_child, right_child) = if i > 0 {
+            (i - 1, Node::load_mut(self.children[i - 1], pager)?, child)
+        } else {
+            let right_child = Node::load_mut(self.children[i + 1], pager)?;
+            (i, child, right_child)
+        };
+
+        // O irmão da direita é absorvido pelo da esquerda
+        right_child.free(pager)?;
+
+        let separator = self.keys.remove(idx);
+        if !left_child.is_leaf || pager.engine() == Engine::BTree {
+            left_child.keys.push(separator);
+            if !self.has_separators_only(pager) {
+                left_child.values.push(self.values.remove(idx));
+            }
+        } else {
+            // Na B+Tree o separador entre duas folhas é só uma cópia da primeira chave da folha da
+            // direita, e a folha da esquerda passa a apontar para a seguinte da direita
+            left_child.next = right_child.next;
+        }
+        left_child.keys.extend(right_child.keys);
+        left_child.values.extend(right_child.values);
+        left_child.children.extend(right_child.children);
+        left_child.counts.extend(right_child.counts);
+        self.children.remove(idx + 1);
+        self.counts.remove(idx + 1);
+
+        if left_child.is_overfull(pager) {
+            self.split_child(idx, left_child, pager)
+        } else {
+            self.counts[idx] = left_child.count();
+            self.children[idx] = left_child.save(pager)?;
+            Ok(())
+        }
     }
+
 }
 
 
@@ -450,7 +1026,7 @@ impl Node {
 mod tests {
     use super::*;
     use crate::db::create_database;
-    use crate::pager::Pager;
+    use crate::pager::{Backend, CorruptPage, Durability, Layout, Pager};
     use std::fs;
     use std::path::Path;
 
@@ -459,13 +1035,54 @@ mod tests {
         if Path::new(&filename).exists() {
             fs::remove_file(&filename).unwrap();
         }
-        create_database(db_name).expect("Não foi possível criar o banco de dados de teste.");
+        create_database(db_name, Layout::default()).expect("Não foi possível criar o banco de dados de teste.");
 
-        let pager = Pager::new(db_name); 
+        let pager = Pager::new(db_name).unwrap(); 
         let btree = BTree::default();
         (btree, pager, filename)
     }
 
+    // Confere as invariantes da árvore e retorna a quantidade de chaves da subárvore:
+    // nós cabem na página, chaves ordenadas dentro dos limites do pai, folhas na mesma profundidade
+    // e contagens das subárvores corretas.
+    // Na B+Tree as folhas podem conter o separador à sua esquerda e os nós internos não têm valores
+    fn check_subtree(offset: u64, pager: &mut Pager, bounds: (Option<&[u8]>, Option<&[u8]>), depth: usize, leaf_depth: &mut Option<usize>) -> usize {
+        let node = Node::load(offset, pager).unwrap();
+        let bplus = pager.engine() == Engine::BPlusTree;
+        assert!(node_page::encoded_size(&node) <= pager.page_data_size());
+        assert!(node.keys.len() <= max_keys(pager.order()));
+        assert!(!node.keys.is_empty() || depth == 0, "Nó vazio fora da raiz");
+        assert!(node.keys.windows(2).all(|w| w[0] < w[1]));
+        let low_inclusive = bplus && node.is_leaf;
+        assert!(bounds.0.is_none_or(|low| node.keys.first().is_none_or(|k| k.as_slice() > low || (low_inclusive && k == low))));
+        assert!(bounds.1.is_none_or(|high| node.keys.last().is_none_or(|k| k.as_slice() < high)));
+
+        if node.is_leaf {
+            assert_eq!(*leaf_depth.get_or_insert(depth), depth, "Folhas em profundidades diferentes");
+            assert_eq!(node.values.len(), node.keys.len());
+            return node.keys.len();
+        }
+
+        assert_eq!(node.children.len(), node.keys.len() + 1);
+        assert_eq!(node.values.len(), if bplus { 0 } else { node.keys.len() });
+        let mut count = if bplus { 0 } else { node.keys.len() };
+        for (i, &child) in node.children.iter().enumerate() {
+            let low = if i == 0 { bounds.0 } else { Some(node.keys[i - 1].as_slice()) };
+            let high = if i == node.keys.len() { bounds.1 } else { Some(node.keys[i].as_slice()) };
+            let child_count = check_subtree(child, pager, (low, high), depth + 1, leaf_depth);
+            assert_eq!(node.counts[i], child_count as u64, "Contagem da subárvore divergente");
+            count += child_count;
+        }
+        count
+    }
+
+    fn check_tree(tree: &BTree, pager: &mut Pager) -> usize {
+        match tree.root {
+            Some(root) => check_subtree(root, pager, (None, None), 0, &mut None),
+            None => 0,
+        }
+    }
+
     fn teardown_test(filename: &str) {
         if Path::new(filename).exists() {
             fs::remove_file(filename).unwrap();
@@ -476,13 +1093,27 @@ mod tests {
     fn test_delete_logic_simple() {
         let (mut tree, mut pager, filename) = setup_test("test_simple_del");
 
-        tree.insert("Key1".to_string(), "Val1".to_string(), &mut pager).unwrap();
-        tree.insert("Key2".to_string(), "Val2".to_string(), &mut pager).unwrap();
+        tree.insert(b"Key1".to_vec(), b"Val1".to_vec(), &mut pager).unwrap();
+        tree.insert(b"Key2".to_vec(), b"Val2".to_vec(), &mut pager).unwrap();
 
-        tree.delete("Key1".to_string(), &mut pager).unwrap();
+        tree.delete(b"Key1".to_vec(), &mut pager).unwrap();
 
-        assert_eq!(tree.search("Key1", &mut pager), None);
-        assert_eq!(tree.search("Key2", &mut pager), Some("Val2".to_string()));
+        assert_eq!(tree.search(b"Key1", &mut pager).unwrap(), None);
+        assert_eq!(tree.search(b"Key2", &mut pager).unwrap(), Some(b"Val2".to_vec()));
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_insert_existing_key_replaces_value() {
+        let (mut tree, mut pager, filename) = setup_test("test_upsert");
+
+        tree.insert(b"Key1".to_vec(), b"Val1".to_vec(), &mut pager).unwrap();
+        tree.insert(b"Key1".to_vec(), b"Val2".repeat(1000), &mut pager).unwrap();
+        tree.insert(b"Key1".to_vec(), b"Val3".to_vec(), &mut pager).unwrap();
+
+        assert_eq!(tree.search(b"Key1", &mut pager).unwrap(), Some(b"Val3".to_vec()));
+        assert_eq!(pager.key_count(), 1);
 
         teardown_test(&filename);
     }
@@ -494,16 +1125,16 @@ mod tests {
         println!(">>> INICIANDO INSERÇÃO (0..20) <<<");
         for i in 0..20 {
             let k = format!("{:03}", i);
-            tree.insert(k.clone(), k, &mut pager).unwrap();
+            tree.insert(k.clone().into_bytes(), k.into_bytes(), &mut pager).unwrap();
         }
         
         println!(">>> INICIANDO DELEÇÃO DOS PARES (0, 2, 4... 18) <<<");
         for i in (0..20).step_by(2) {
             let k = format!("{:03}", i);
             println!("--- Deletando {} ---", k);
-            tree.delete(k.clone(), &mut pager).unwrap();
+            tree.delete(k.clone().into_bytes(), &mut pager).unwrap();
             
-            if tree.search(&k, &mut pager).is_some() {
+            if tree.search(k.as_bytes(), &mut pager).unwrap().is_some() {
                 panic!("ERRO CRÍTICO: Acabei de deletar {}, mas ela ainda é encontrada!", k);
             }
         }
@@ -511,7 +1142,7 @@ mod tests {
         println!(">>> VERIFICAÇÃO FINAL <<<");
         for i in 0..20 {
             let k = format!("{:03}", i);
-            let resultado = tree.search(&k, &mut pager);
+            let resultado = tree.search(k.as_bytes(), &mut pager).unwrap();
 
             if i % 2 == 0 {
                 if resultado.is_some() {
@@ -526,4 +1157,633 @@ mod tests {
         
         teardown_test(&filename);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_overflow_values() {
+        let (mut tree, mut pager, filename) = setup_test("test_overflow");
+
+        // Valores bem maiores que uma página, suficientes para forçar splits
+        for i in 0..12 {
+            let k = format!("{:03}", i).into_bytes();
+            let v = k.repeat(3000 + i * 100);
+            tree.insert(k, v, &mut pager).unwrap();
+        }
+
+        for i in 0..12 {
+            let k = format!("{:03}", i).into_bytes();
+            assert_eq!(tree.search(&k, &mut pager).unwrap(), Some(k.repeat(3000 + i * 100)));
+        }
+
+        let long_key = b"k".repeat(MAX_KEY_SIZE + 1);
+        assert!(tree.insert(long_key, b"v".to_vec(), &mut pager).is_err());
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_long_keys_in_overflow_chains() {
+        use rand::Rng;
+        use std::collections::BTreeMap;
+
+        let name = "test_long_keys";
+        let filename = format!("./databases/{name}.kvdb");
+        teardown_test(&filename);
+        // Chaves curtas, chaves que dividem o prefixo guardado na página (e só são ordenadas pela
+        // cadeia) e chaves maiores que uma página
+        let key = |i: usize| match i % 4 {
+            0 => format!("{i:04}").into_bytes(),
+            1 => format!("{}{i:04}", "p".repeat(node_page::MAX_INLINE_KEY_SIZE - 16)).into_bytes(),
+            2 => format!("{}{i:04}", "p".repeat(300)).into_bytes(),
+            _ => format!("{}{i:04}", "q".repeat(5000)).into_bytes(),
+        };
+        for engine in [Engine::BTree, Engine::BPlusTree] {
+            create_database(name, Layout { engine, ..Default::default() }).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let mut tree = BTree::default();
+            let mut model = BTreeMap::new();
+            let mut rng = rand::rng();
+
+            for step in 0..1500 {
+                let k = key(rng.random_range(0..200));
+                if rng.random_bool(0.6) {
+                    let v = b"v".repeat(rng.random_range(0..700));
+                    tree.insert(k.clone(), v.clone(), &mut pager).unwrap();
+                    model.insert(k, v);
+                } else {
+                    tree.delete(k.clone(), &mut pager).unwrap();
+                    model.remove(&k);
+                }
+
+                if step % 500 == 0 {
+                    pager = Pager::new(name).unwrap();
+                }
+            }
+
+            assert_eq!(check_tree(&tree, &mut pager), model.len());
+            for i in 0..200 {
+                assert_eq!(tree.search(&key(i), &mut pager).unwrap(), model.get(&key(i)).cloned(), "{engine:?} {i}");
+            }
+            let entries: Vec<_> = tree.cursor(&mut pager).unwrap().map(|entry| entry.unwrap()).collect();
+            assert!(entries.iter().map(|(k, v)| (k, v)).eq(model.iter()));
+            for (rank, k) in model.keys().enumerate().step_by(7) {
+                assert_eq!(tree.rank(k, &mut pager).unwrap(), rank as u64);
+                assert_eq!(tree.select(rank as u64, &mut pager).unwrap().as_ref(), Some(k));
+            }
+            let low = key(2);
+            let range = tree.range(low.clone().., &mut pager).unwrap().map(|entry| entry.unwrap().0);
+            assert!(range.eq(model.range(low..).map(|(k, _)| k.clone())));
+
+            // As cadeias das chaves são liberadas junto com as versões dos nós: repetir a mesma carga
+            // reaproveita as páginas liberadas em vez de aumentar o arquivo
+            for k in model.keys() {
+                tree.delete(k.clone(), &mut pager).unwrap();
+            }
+            let mut sizes = Vec::new();
+            for _ in 0..2 {
+                for i in 0..200 {
+                    tree.insert(key(i), b"v".repeat(i), &mut pager).unwrap();
+                }
+                for i in (0..200).step_by(3) {
+                    tree.insert(key(i), b"w".repeat(i), &mut pager).unwrap();
+                }
+                for i in 0..200 {
+                    tree.delete(key(i), &mut pager).unwrap();
+                }
+                assert_eq!(tree.root, None);
+                sizes.push(fs::metadata(&filename).unwrap().len());
+            }
+            assert_eq!(sizes[0], sizes[1], "{engine:?}");
+
+            // A compactação grava cadeias novas para as chaves no arquivo de destino
+            for i in 0..200 {
+                tree.insert(key(i), b"v".repeat(i), &mut pager).unwrap();
+            }
+            drop(pager);
+            crate::db::compact_database(name).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let tree = BTree::new(pager.root_offset());
+            assert_eq!(check_tree(&tree, &mut pager), 200);
+            for i in 0..200 {
+                assert_eq!(tree.search(&key(i), &mut pager).unwrap(), Some(b"v".repeat(i)));
+            }
+
+            teardown_test(&filename);
+        }
+    }
+
+    #[test]
+    fn test_free_page_reuse() {
+        let (mut tree, mut pager, filename) = setup_test("test_free_list");
+        let mut sizes = Vec::new();
+
+        for round in 0..10 {
+            for i in 0..50 {
+                let k = format!("{:03}", i).into_bytes();
+                tree.insert(k.clone(), k.repeat(400), &mut pager).unwrap();
+            }
+            for i in 0..50 {
+                tree.delete(format!("{:03}", i).into_bytes(), &mut pager).unwrap();
+            }
+            assert_eq!(tree.search(b"000", &mut pager).unwrap(), None);
+
+            // Reabre o arquivo para garantir que a lista livre foi persistida
+            if round % 2 == 0 {
+                pager = Pager::new("test_free_list").unwrap();
+            }
+            sizes.push(fs::metadata(&filename).unwrap().len());
+        }
+
+        assert_eq!(sizes[1], sizes[9], "O arquivo não deveria crescer ao reaproveitar páginas: {sizes:?}");
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_random_operations_against_model() {
+        use rand::Rng;
+        use std::collections::BTreeMap;
+
+        let (mut tree, mut pager, filename) = setup_test("test_random_ops");
+        let mut model = BTreeMap::new();
+        let mut rng = rand::rng();
+
+        for step in 0..3000 {
+            let k = format!("{:04}", rng.random_range(0..300)).into_bytes();
+            if rng.random_bool(0.6) {
+                let v = k.repeat(rng.random_range(1..400));
+                tree.insert(k.clone(), v.clone(), &mut pager).unwrap();
+                model.insert(k, v);
+            } else {
+                tree.delete(k.clone(), &mut pager).unwrap();
+                model.remove(&k);
+            }
+
+            // Reabre periodicamente para exercitar a lista livre persistida
+            if step % 500 == 0 {
+                pager = Pager::new("test_random_ops").unwrap();
+            }
+        }
+
+        for i in 0..300 {
+            let k = format!("{:04}", i).into_bytes();
+            assert_eq!(tree.search(&k, &mut pager).unwrap(), model.get(&k).cloned(), "Chave {k:?} divergiu do modelo");
+        }
+        assert_eq!(pager.key_count(), model.len() as u64);
+        assert_eq!(check_tree(&tree, &mut pager), model.len());
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_corrupted_page_is_reported() {
+        let (mut tree, mut pager, filename) = setup_test("test_corruption");
+
+        for i in 0..20 {
+            let k = format!("{:03}", i).into_bytes();
+            tree.insert(k.clone(), k, &mut pager).unwrap();
+        }
+        let root_id = tree.root.unwrap();
+
+        // Inverte um bit dentro da página da raiz
+        let mut data = fs::read(&filename).unwrap();
+        data[root_id as usize + 100] ^= 0x01;
+        fs::write(&filename, data).unwrap();
+
+        let mut pager = Pager::new("test_corruption").unwrap();
+        let err = tree.search(b"005", &mut pager).expect_err("A corrupção deveria ser detectada");
+        let corrupt = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<CorruptPage>())
+            .expect("Erro deveria ser do tipo CorruptPage");
+        assert_eq!(corrupt.offset, root_id);
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_hot_keys_are_served_from_cache() {
+        let (mut tree, mut pager, filename) = setup_test("test_page_cache");
+
+        for i in 0..200 {
+            let k = format!("{:03}", i).into_bytes();
+            tree.insert(k.clone(), k, &mut pager).unwrap();
+        }
+
+        // Paginador novo: o primeiro acesso precisa ler o caminho inteiro do arquivo
+        let mut pager = Pager::new("test_page_cache").unwrap();
+        assert_eq!(tree.search(b"123", &mut pager).unwrap(), Some(b"123".to_vec()));
+        let first = pager.cache_stats();
+        assert!(first.misses > 0);
+
+        let first_nodes = pager.node_cache_stats();
+        for _ in 0..100 {
+            assert_eq!(tree.search(b"123", &mut pager).unwrap(), Some(b"123".to_vec()));
+        }
+        // As buscas são atendidas pelas páginas em cache, sem tocar o arquivo nem decodificar nós
+        let stats = pager.cache_stats();
+        assert_eq!(stats.misses, first.misses);
+        assert!(stats.hits >= first.hits + 100);
+        assert_eq!(pager.node_cache_stats(), first_nodes);
+
+        // Sem cache, toda busca volta ao arquivo
+        pager.set_cache_capacity(0);
+        tree.search(b"123", &mut pager).unwrap();
+        assert!(pager.cache_stats().misses > stats.misses);
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_mmap_backend() {
+        let (mut tree, mut pager, filename) = setup_test("test_mmap");
+        // Sem cache, todas as leituras passam pelo mapeamento, que precisa acompanhar o crescimento do arquivo
+        pager.set_cache_capacity(0);
+        pager.set_backend(Backend::Mmap).unwrap();
+
+        for i in 0..300 {
+            let k = format!("{:03}", i).into_bytes();
+            tree.insert(k.clone(), k.repeat(if i % 50 == 0 { 1000 } else { 1 }), &mut pager).unwrap();
+        }
+        for i in (0..300).step_by(3) {
+            tree.delete(format!("{:03}", i).into_bytes(), &mut pager).unwrap();
+        }
+
+        let mut pager = Pager::new("test_mmap").unwrap();
+        pager.set_cache_capacity(0);
+        pager.set_backend(Backend::Mmap).unwrap();
+        for i in 0..300 {
+            let k = format!("{:03}", i).into_bytes();
+            let expected = (i % 3 != 0).then(|| k.repeat(if i % 50 == 0 { 1000 } else { 1 }));
+            assert_eq!(tree.search(&k, &mut pager).unwrap(), expected);
+        }
+
+        teardown_test(&filename);
+    }
+
+    // Compara as leituras pelo arquivo e pelo mapeamento, com os caches desligados para que toda
+    // busca chegue ao backend: cargo test --release bench_file_vs_mmap_reads -- --ignored --nocapture
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_file_vs_mmap_reads() {
+        use std::time::Instant;
+
+        let (mut tree, mut pager, filename) = setup_test("bench_backends");
+        pager.set_durability(Durability::None);
+        let keys: Vec<Vec<u8>> = (0..20_000).map(|i| format!("{:06}", i * 7919 % 20_000).into_bytes()).collect();
+        for k in &keys {
+            tree.insert(k.clone(), k.repeat(8), &mut pager).unwrap();
+        }
+
+        for backend in [Backend::File, Backend::Mmap] {
+            let mut pager = Pager::new("bench_backends").unwrap();
+            pager.set_cache_capacity(0);
+            pager.set_backend(backend).unwrap();
+
+            let start = Instant::now();
+            for _ in 0..5 {
+                for k in &keys {
+                    assert!(tree.search(k, &mut pager).unwrap().is_some());
+                }
+            }
+            let elapsed = start.elapsed();
+            println!("{backend:?}: {:?} por busca", elapsed / (5 * keys.len() as u32));
+        }
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_nodes_split_and_merge_by_size() {
+        use rand::Rng;
+        use std::collections::BTreeMap;
+
+        let filename = "./databases/test_split_by_size.kvdb".to_string();
+        teardown_test(&filename);
+        // Ordem mínima: o limite de chaves e o tamanho em bytes disputam a decisão de dividir
+        for order in [DEFAULT_ORDER, 2] {
+            create_database("test_split_by_size", Layout { order, ..Default::default() }).unwrap();
+            let mut pager = Pager::new("test_split_by_size").unwrap();
+            let mut tree = BTree::default();
+            let mut model = BTreeMap::new();
+            let mut rng = rand::rng();
+
+            for step in 0..2000 {
+                // Chaves de até 256 bytes e valores que ficam no nó (até 512 bytes) ou vão para overflow
+                let k = format!("{:03}", rng.random_range(0..200)).into_bytes().repeat(rng.random_range(1..=85));
+                if rng.random_bool(0.65) {
+                    let v = b"v".repeat(rng.random_range(0..700));
+                    tree.insert(k.clone(), v.clone(), &mut pager).unwrap();
+                    model.insert(k, v);
+                } else {
+                    tree.delete(k.clone(), &mut pager).unwrap();
+                    model.remove(&k);
+                }
+
+                if step % 250 == 0 {
+                    assert_eq!(check_tree(&tree, &mut pager), model.len());
+                }
+            }
+
+            assert_eq!(check_tree(&tree, &mut pager), model.len());
+            for (k, v) in &model {
+                assert_eq!(tree.search(k, &mut pager).unwrap().as_ref(), Some(v));
+            }
+
+            // Remover tudo esvazia a árvore, fundindo os nós até sobrar só a raiz
+            let keys: Vec<Vec<u8>> = model.keys().cloned().collect();
+            for k in keys {
+                tree.delete(k, &mut pager).unwrap();
+            }
+            assert_eq!(tree.root, None);
+            assert_eq!(pager.key_count(), 0);
+
+            teardown_test(&filename);
+        }
+    }
+
+    #[test]
+    fn test_small_entries_fill_pages() {
+        let (mut tree, mut pager, filename) = setup_test("test_fill_pages");
+
+        for i in 0..2000 {
+            let k = format!("{:05}", i).into_bytes();
+            tree.insert(k.clone(), k, &mut pager).unwrap();
+        }
+
+        // Entradas de ~30 bytes: cada página de 4 KiB guarda dezenas delas, e não só 2 * ordem - 1
+        let root = Node::load(tree.root.unwrap(), &mut pager).unwrap();
+        assert!(root.children.len() > 1 && root.children.len() < 40);
+        let leaf = Node::load(root.children[0], &mut pager).unwrap();
+        assert!(leaf.is_leaf);
+        assert!(leaf.keys.len() > 50);
+        assert_eq!(check_tree(&tree, &mut pager), 2000);
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_bplus_tree_keeps_values_in_leaves() {
+        use rand::Rng;
+        use std::collections::BTreeMap;
+
+        let name = "test_bplus";
+        let filename = format!("./databases/{name}.kvdb");
+        teardown_test(&filename);
+        // Ordem mínima e ordem padrão: a divisão por quantidade de chaves e por tamanho
+        for order in [2, DEFAULT_ORDER] {
+            create_database(name, Layout { order, engine: Engine::BPlusTree, ..Default::default() }).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let mut tree = BTree::default();
+            let mut model = BTreeMap::new();
+            let mut rng = rand::rng();
+
+            for step in 0..3000 {
+                let k = format!("{:04}", rng.random_range(0..400)).into_bytes().repeat(rng.random_range(1..=60));
+                if rng.random_bool(0.6) {
+                    let v = b"v".repeat(rng.random_range(0..700));
+                    tree.insert(k.clone(), v.clone(), &mut pager).unwrap();
+                    model.insert(k, v);
+                } else {
+                    tree.delete(k.clone(), &mut pager).unwrap();
+                    model.remove(&k);
+                }
+
+                if step % 500 == 0 {
+                    pager = Pager::new(name).unwrap();
+                    assert_eq!(check_tree(&tree, &mut pager), model.len());
+                }
+            }
+
+            assert_eq!(check_tree(&tree, &mut pager), model.len());
+            assert_eq!(pager.key_count(), model.len() as u64);
+            for (k, v) in &model {
+                assert_eq!(tree.search(k, &mut pager).unwrap().as_ref(), Some(v));
+            }
+
+            // O cursor passa de uma folha para a seguinte e devolve as chaves em ordem
+            let keys = tree.cursor(&mut pager).unwrap().map(|entry| entry.unwrap().0);
+            assert!(keys.eq(model.keys().cloned()));
+
+            drop(pager);
+            crate::db::compact_database(name).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let mut tree = BTree::new(pager.root_offset());
+            assert_eq!(check_tree(&tree, &mut pager), model.len());
+
+            for k in model.keys() {
+                tree.delete(k.clone(), &mut pager).unwrap();
+            }
+            assert_eq!(tree.root, None);
+
+            teardown_test(&filename);
+        }
+    }
+
+    #[test]
+    fn test_len_rank_and_select() {
+        use rand::Rng;
+        use std::collections::BTreeSet;
+
+        let name = "test_order_statistics";
+        let filename = format!("./databases/{name}.kvdb");
+        for engine in [Engine::BTree, Engine::BPlusTree] {
+            teardown_test(&filename);
+            create_database(name, Layout { order: 3, engine, ..Default::default() }).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let mut tree = BTree::default();
+            assert_eq!(tree.len(&mut pager).unwrap(), 0);
+            assert_eq!(tree.select(0, &mut pager).unwrap(), None);
+
+            let mut model = BTreeSet::new();
+            let mut rng = rand::rng();
+            for _ in 0..2000 {
+                let k = format!("{:04}", rng.random_range(0..500) * 2).into_bytes();
+                if rng.random_bool(0.7) {
+                    tree.insert(k.clone(), k.clone(), &mut pager).unwrap();
+                    model.insert(k);
+                } else {
+                    tree.delete(k.clone(), &mut pager).unwrap();
+                    model.remove(&k);
+                }
+            }
+
+            assert_eq!(check_tree(&tree, &mut pager), model.len());
+            assert_eq!(tree.len(&mut pager).unwrap(), model.len() as u64);
+            for (k, key) in model.iter().enumerate() {
+                assert_eq!(tree.select(k as u64, &mut pager).unwrap().as_ref(), Some(key), "{engine:?}");
+                assert_eq!(tree.rank(key, &mut pager).unwrap(), k as u64, "{engine:?}");
+            }
+            assert_eq!(tree.select(model.len() as u64, &mut pager).unwrap(), None);
+
+            // Chaves ausentes (ímpares) ficam entre as vizinhas
+            for probe in [1, 501, 999, 1001] {
+                let key = format!("{probe:04}").into_bytes();
+                let expected = model.range(..key.clone()).count() as u64;
+                assert_eq!(tree.rank(&key, &mut pager).unwrap(), expected);
+            }
+        }
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_bulk_load_builds_packed_tree() {
+        let name = "test_bulk_load";
+        let filename = format!("./databases/{name}.kvdb");
+        let entries = |n: usize| {
+            (0..n).map(|i| {
+                let size = match i % 50 { 0 => 9000, 1 => 900, _ => i % 40 };
+                (format!("{i:06}").into_bytes(), b"v".repeat(size))
+            })
+        };
+
+        for engine in [Engine::BTree, Engine::BPlusTree] {
+            for order in [3, DEFAULT_ORDER] {
+                teardown_test(&filename);
+                create_database(name, Layout { order, engine, ..Default::default() }).unwrap();
+                let mut pager = Pager::new(name).unwrap();
+                let mut tree = BTree::bulk_load(entries(3000), &mut pager).unwrap();
+
+                // Reabre para garantir que a raiz e a contagem foram publicadas
+                pager = Pager::new(name).unwrap();
+                assert_eq!(tree.root, pager.root_offset());
+                assert_eq!(pager.key_count(), 3000);
+                assert_eq!(check_tree(&tree, &mut pager), 3000);
+                assert_eq!(tree.len(&mut pager).unwrap(), 3000);
+                for (k, v) in entries(3000) {
+                    assert_eq!(tree.search(&k, &mut pager).unwrap(), Some(v), "{engine:?} {order}");
+                }
+                let loaded_size = fs::metadata(&filename).unwrap().len();
+
+                // A árvore continua operável depois da carga
+                tree.insert(b"999999".to_vec(), b"fim".to_vec(), &mut pager).unwrap();
+                tree.delete(b"000000".to_vec(), &mut pager).unwrap();
+                assert_eq!(check_tree(&tree, &mut pager), 3000);
+                drop(pager);
+
+                // Inserções uma a uma deixam as folhas pela metade e o arquivo maior
+                teardown_test(&filename);
+                create_database(name, Layout { order, engine, ..Default::default() }).unwrap();
+                let mut pager = Pager::new(name).unwrap();
+                let mut tree = BTree::default();
+                for (k, v) in entries(3000) {
+                    tree.insert(k, v, &mut pager).unwrap();
+                }
+                drop(pager);
+                crate::db::compact_database(name).unwrap();
+                assert!(loaded_size < fs::metadata(&filename).unwrap().len(), "{engine:?} {order}");
+
+                // Banco não vazio
+                let mut pager = Pager::new(name).unwrap();
+                assert!(BTree::bulk_load(entries(10), &mut pager).is_err());
+            }
+        }
+
+        // Entrada fora de ordem ou repetida é rejeitada sem publicar nada
+        for bad in [vec!["b", "a"], vec!["a", "a"]] {
+            teardown_test(&filename);
+            create_database(name, Layout::default()).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let input = bad.into_iter().map(|k| (k.as_bytes().to_vec(), k.as_bytes().to_vec()));
+            assert!(BTree::bulk_load(input, &mut pager).is_err());
+            assert_eq!(Pager::new(name).unwrap().root_offset(), None);
+        }
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_failed_publish_keeps_free_list() {
+        let (mut tree, mut pager, filename) = setup_test("test_failed_publish");
+        for i in 0..200 {
+            tree.insert(format!("{i:03}").into_bytes(), b"v".repeat(i * 5), &mut pager).unwrap();
+        }
+        for i in 0..150 {
+            tree.insert(format!("tmp{i:03}").into_bytes(), b"t".repeat(10_000), &mut pager).unwrap();
+        }
+        for i in 0..150 {
+            tree.delete(format!("tmp{i:03}").into_bytes(), &mut pager).unwrap();
+        }
+        let (root, free_pages) = (tree.root, pager.free_page_count());
+
+        // A transação é montada normalmente, mas a gravação da publicação falha
+        let transaction = tree.begin(&pager);
+        let mut batch = WriteBatch::new();
+        for i in 0..100 {
+            batch.delete(format!("{i:03}"));
+        }
+        batch.put("novo", "x".repeat(5000));
+        tree.stage(batch, &mut pager).unwrap();
+        pager.set_file(fs::File::open(&filename).unwrap());
+        assert!(tree.commit(transaction, &mut pager).is_err());
+        assert_eq!(tree.root, root);
+
+        // Nem as páginas da árvore publicada nem as da lista livre gravada entram na lista livre
+        assert_eq!(pager.free_page_count(), free_pages);
+        pager.set_file(fs::File::options().read(true).write(true).open(&filename).unwrap());
+        for i in 0..300 {
+            tree.insert(format!("novo{i:03}").into_bytes(), b"n".repeat(700), &mut pager).unwrap();
+        }
+        for i in 0..200 {
+            assert_eq!(tree.search(format!("{i:03}").as_bytes(), &mut pager).unwrap(), Some(b"v".repeat(i * 5)));
+        }
+        let mut reopened = Pager::new("test_failed_publish").unwrap();
+        assert_eq!(check_tree(&tree, &mut reopened), 500);
+
+        teardown_test(&filename);
+    }
+
+    #[test]
+    fn test_write_batch_is_atomic() {
+        let (mut tree, mut pager, filename) = setup_test("test_write_batch");
+        for i in 0..200 {
+            tree.insert(format!("{i:03}").into_bytes(), b"v".repeat(i * 5), &mut pager).unwrap();
+        }
+        // Valores grandes inseridos e removidos deixam páginas livres para o lote reaproveitar
+        for i in 0..150 {
+            tree.insert(format!("tmp{i:03}").into_bytes(), b"t".repeat(10_000), &mut pager).unwrap();
+        }
+        for i in 0..150 {
+            tree.delete(format!("tmp{i:03}").into_bytes(), &mut pager).unwrap();
+        }
+        let root = tree.root;
+        let free_pages = pager.free_page_count();
+        let file_size = fs::metadata(&filename).unwrap().len();
+
+        // Um lote que falha no meio não deixa rastro, nem na memória nem no arquivo
+        let mut batch = WriteBatch::new();
+        for i in 0..100 {
+            batch.delete(format!("{i:03}"));
+        }
+        batch.put("novo", "x".repeat(5000)).put("k".repeat(MAX_KEY_SIZE + 1), "");
+        assert!(tree.write(batch, &mut pager).is_err());
+        assert_eq!(tree.root, root);
+        assert_eq!(pager.key_count(), 200);
+        // As páginas gravadas pelas operações desfeitas voltam para a lista livre
+        assert_eq!(pager.free_page_count(), free_pages);
+        assert_eq!(fs::metadata(&filename).unwrap().len(), file_size);
+        let mut reopened = Pager::new("test_write_batch").unwrap();
+        assert_eq!(reopened.root_offset(), root);
+        assert_eq!(check_tree(&tree, &mut reopened), 200);
+
+        // Um lote bem-sucedido publica todas as operações, na ordem em que foram incluídas
+        let mut batch = WriteBatch::new();
+        for i in 0..100 {
+            batch.delete(format!("{i:03}"));
+        }
+        batch
+            .put("100", "substituído")
+            .put("novo", "x".repeat(5000))
+            .delete("novo")
+            .put("novo", "y");
+        tree.write(batch, &mut pager).unwrap();
+        assert_eq!(check_tree(&tree, &mut pager), 101);
+        assert_eq!(pager.key_count(), 101);
+        assert_eq!(tree.search(b"050", &mut pager).unwrap(), None);
+        assert_eq!(tree.search(b"100", &mut pager).unwrap().as_deref(), Some("substituído".as_bytes()));
+        assert_eq!(tree.search(b"novo", &mut pager).unwrap().as_deref(), Some(&b"y"[..]));
+        assert_eq!(tree.search(b"199", &mut pager).unwrap(), Some(b"v".repeat(995)));
+
+        teardown_test(&filename);
+    }
+}
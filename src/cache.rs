@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+// Contadores de uso do cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// Cache limitado que descarta o item usado há mais tempo (LRU).
+// Cada acesso recebe um número crescente (`tick`); o menor número em `order`
+// é sempre o item menos recentemente usado.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    // Capacidade zero desliga o cache
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    // Altera a capacidade, descartando os itens excedentes
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    // Busca um item e o marca como o mais recentemente usado
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if !self.touch(key) {
+            return None;
+        }
+        self.peek(key)
+    }
+
+    // Marca o item como o mais recentemente usado, contando o acerto ou a falta
+    pub fn touch(&mut self, key: &K) -> bool {
+        let Some((_, tick)) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return false;
+        };
+
+        self.stats.hits += 1;
+        self.tick += 1;
+        let old = std::mem::replace(tick, self.tick);
+        let key = self.order.remove(&old).unwrap();
+        self.order.insert(self.tick, key);
+        true
+    }
+
+    // Consulta um item sem alterar a ordem de uso nem os contadores
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.tick += 1;
+        if let Some((_, old)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.order.remove(&old);
+        }
+        self.order.insert(self.tick, key);
+        self.evict();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        Some(value)
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let (_, key) = self.order.pop_first().unwrap();
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "um");
+        cache.insert(2, "dois");
+        assert_eq!(cache.get(&1), Some(&"um"));
+
+        // 2 é o menos recentemente usado
+        cache.insert(3, "três");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"um"));
+        assert_eq!(cache.get(&3), Some(&"três"));
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+
+        cache.set_capacity(0);
+        assert_eq!(cache.get(&1), None);
+        cache.insert(4, "quatro");
+        assert_eq!(cache.get(&4), None);
+    }
+}
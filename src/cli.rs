@@ -29,6 +29,10 @@ fn event_loop(key: KeyEvent, app: &mut App) -> Option<Result<bool>> {
             Some(DatabaseListingOptions::ChooseDb) => {
                 app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SelectCommand);
             }
+            Some(DatabaseListingOptions::Failure) => {
+                app.option_highlighted = 0;
+                app.current_screen = CurrentScreen::Main(MainMenu::FailureMessage);
+            }
             Some(DatabaseListingOptions::Exit) => {
                 app.current_screen = CurrentScreen::Main(MainMenu::OptionsList);
             }
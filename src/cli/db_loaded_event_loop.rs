@@ -1,19 +1,47 @@
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Bound;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::app::{App, CurrentScreen, DatabaseCommands::*, DatabasePrompt, MainMenu};
 
-use crate::btree::BTree;
-use crate::cli::menus::database_commands;
-use crate::cli::shared::user_input;
+use crate::cli::menus::{database_commands, load_database};
+use crate::db::compact_database;
+use crate::snapshot::Snapshot;
+use crate::cli::shared::{display_bytes, user_input};
+
+// "@checkpoint consulta" lê do checkpoint em vez do banco atual; "@@" escapa uma consulta do banco
+// atual que começa com "@" ("@@chave" busca "@chave")
+fn split_checkpoint(input: &str) -> (Option<&str>, &str) {
+    if input.starts_with("@@") {
+        return (None, &input[1..]);
+    }
+    match input.strip_prefix('@') {
+        Some(rest) => {
+            let (name, query) = rest.split_once(' ').unwrap_or((rest, ""));
+            (Some(name), query)
+        }
+        None => (None, input),
+    }
+}
 
 fn _search(app: &mut App) {
-    if let Some(pager) = &mut app.loaded_db {
-        match app.index.search(&app.input, pager) {
-            Some(s) => {
-                app.search_result = s;
+    if let Some(db) = &mut app.loaded_db {
+        let result = match split_checkpoint(&app.input) {
+            (Some(checkpoint), key) => db.open_checkpoint(checkpoint).and_then(|mut snapshot| snapshot.search(key)),
+            (None, key) => db.search(key),
+        };
+        match result {
+            Ok(Some(value)) => {
+                app.search_result = display_bytes(&value);
                 app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView);
             }
-            None => {
+            Ok(None) => {
+                app.failure_message = format!("Chave '{}' não encontrada.", app.input);
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+            }
+            Err(e) => {
+                app.failure_message = format!("Falha na busca: {e}");
                 app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
             }
         }
@@ -33,35 +61,429 @@ fn _insert(app: &mut App) {
             .expect("Não foi possível converter o nome do arquivo para o padrão Unicode.")
             .to_string();
 
-        match std::fs::read_to_string(path) {
+        // O conteúdo é gravado como está, seja texto ou binário
+        match std::fs::read(path) {
             Ok(data) => {
-                if let Some(pager) = &mut app.loaded_db {
-                    match app.index.insert(key, data, pager) {
+                if let Some(db) = &mut app.loaded_db {
+                    match db.insert(key, data) {
                         Ok(_) => {
+                            app.refresh_len();
                             app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
                         }
-                        Err(_) => {
+                        Err(e) => {
+                            app.failure_message = format!("Falha ao inserir: {e}");
                             app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
                         }
                     }
                 }
              }
-            Err(_) => {
+            Err(e) => {
+                app.failure_message = format!("Falha ao ler o arquivo: {e}");
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+            }
+        }
+    }
+}
+
+fn _delete(app: &mut App) {
+    if let Some(db) = &mut app.loaded_db {
+        match db.delete(app.input.clone()) {
+            Ok(_) => {
+                app.refresh_len();
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
+            }
+            Err(e) => {
+                app.failure_message = format!("Falha ao remover: {e}");
                 app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
             }
         }
     }
 }
 
+// Entradas exibidas por página no SCAN
+const SCAN_PAGE_SIZE: usize = 20;
+
+// Entradas de uma página do SCAN e o início da página seguinte
+type ScanPage = (Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>);
+
+// Páginas do SCAN
+enum Page {
+    First,
+    Next,
+    Prev,
+}
+
+// Consulta do SCAN: um intervalo "início..fim" (fim exclusivo; qualquer ponta pode ser omitida)
+// ou um prefixo. Retorna a página que começa em `from`
+fn scan_page(snapshot: &mut Snapshot, query: &str, from: Option<&[u8]>) -> Result<ScanPage> {
+    let mut range = match query.split_once("..") {
+        Some((start, end)) => {
+            let start = if start.is_empty() { Bound::Unbounded } else { Bound::Included(start.as_bytes().to_vec()) };
+            let end = if end.is_empty() { Bound::Unbounded } else { Bound::Excluded(end.as_bytes().to_vec()) };
+            snapshot.range((start, end))?
+        }
+        None => snapshot.scan_prefix(query)?,
+    };
+    if let Some(from) = from {
+        range.seek(from)?;
+    }
+
+    // Uma entrada a mais indica onde começa a próxima página
+    let mut entries = range.take(SCAN_PAGE_SIZE + 1).collect::<Result<Vec<_>>>()?;
+    let next = (entries.len() > SCAN_PAGE_SIZE).then(|| entries.pop().unwrap().0);
+    Ok((entries, next))
+}
+
+fn _scan(app: &mut App, page: Page) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    let (checkpoint, query) = split_checkpoint(&app.input);
+    if matches!(page, Page::First) {
+        let snapshot = match checkpoint {
+            Some(checkpoint) => db.open_checkpoint(checkpoint),
+            None => db.snapshot(),
+        };
+        match snapshot {
+            Ok(snapshot) => app.scan_snapshot = Some(snapshot),
+            Err(e) => {
+                app.failure_message = format!("Falha na consulta: {e}");
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+                return;
+            }
+        }
+    }
+    let Some(snapshot) = &mut app.scan_snapshot else {
+        return;
+    };
+
+    let from = match page {
+        Page::First => None,
+        Page::Next => match &app.scan_next {
+            Some(next) => Some(next.clone()),
+            None => return,
+        },
+        Page::Prev if app.scan_pages.len() > 1 => app.scan_pages[app.scan_pages.len() - 2].clone(),
+        Page::Prev => return,
+    };
+
+    match scan_page(snapshot, query, from.as_deref()) {
+        Ok((entries, _)) if entries.is_empty() && matches!(page, Page::First) => {
+            app.scan_snapshot = None;
+            app.failure_message = format!("Nenhuma chave encontrada para '{}'.", app.input);
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+        Ok((entries, next)) => {
+            match page {
+                Page::First => app.scan_pages = vec![None],
+                Page::Next => app.scan_pages.push(from),
+                Page::Prev => {
+                    app.scan_pages.pop();
+                }
+            }
+            app.scan_next = next;
+
+            // Uma linha por entrada, com o início do valor (ou o tamanho, se ele não for texto)
+            app.search_result = entries
+                .iter()
+                .map(|(key, value)| {
+                    let key = String::from_utf8_lossy(key);
+                    let Ok(value) = std::str::from_utf8(value) else {
+                        return format!("{key}: <binário, {} bytes>", value.len());
+                    };
+                    let preview: String = value.chars().take(60).map(|c| if c.is_control() { ' ' } else { c }).collect();
+                    let ellipsis = if value.chars().count() > 60 { "…" } else { "" };
+                    format!("{key}: {preview}{ellipsis}")
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            app.vertical_scroll = 0;
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView);
+        }
+        Err(e) => {
+            app.scan_snapshot = None;
+            app.failure_message = format!("Falha na consulta: {e}");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+}
+
+// Movimentos do BROWSE em relação à chave exibida
+enum Browse {
+    Seek,
+    Next,
+    Prev,
+    First,
+    Last,
+}
+
+// Mostra a entrada encontrada pelo cursor; nas pontas da árvore a entrada atual continua na tela.
+// Na busca inicial, "#N" vai direto para a N-ésima chave
+fn _browse(app: &mut App, movement: Browse) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    // Na busca inicial a chave vem da entrada; nos movimentos, é a da entrada exibida
+    let mut key = match movement {
+        Browse::Seek => app.input.as_bytes().to_vec(),
+        _ => app.browse_key.clone(),
+    };
+    if let Browse::Seek = movement
+        && let Some(position) = app.input.strip_prefix('#').and_then(|n| n.parse::<u64>().ok())
+    {
+        match db.select(position.saturating_sub(1)) {
+            Ok(Some(selected)) => key = selected,
+            Ok(None) => {
+                app.failure_message = format!("Não há chave na posição {position}.");
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+                return;
+            }
+            Err(e) => {
+                app.failure_message = format!("Falha ao percorrer o banco de dados: {e}");
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+                return;
+            }
+        }
+    }
+
+    let entry = db.cursor().and_then(|mut cursor| match movement {
+        Browse::Seek => {
+            cursor.seek(&key)?;
+            cursor.next().transpose()
+        }
+        Browse::Next => {
+            cursor.seek(&key)?;
+            match cursor.next().transpose()? {
+                Some((k, _)) if k == key => cursor.next().transpose(),
+                entry => Ok(entry),
+            }
+        }
+        Browse::Prev => {
+            cursor.seek(&key)?;
+            cursor.prev().transpose()
+        }
+        Browse::First => {
+            cursor.seek_first()?;
+            cursor.next().transpose()
+        }
+        Browse::Last => {
+            cursor.seek_last()?;
+            cursor.prev().transpose()
+        }
+    });
+
+    // Posição da entrada encontrada entre todas as chaves
+    let entry = entry.and_then(|entry| match entry {
+        Some((key, value)) => Ok(Some((db.rank(&key)?, db.len()?, key, value))),
+        None => Ok(None),
+    });
+
+    match entry {
+        Ok(Some((rank, len, key, value))) => {
+            app.browse_position = Some((rank, len));
+            app.input = String::from_utf8_lossy(&key).into_owned();
+            app.browse_key = key;
+            app.search_result = display_bytes(&value);
+            app.vertical_scroll = 0;
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView);
+        }
+        Ok(None) if matches!(movement, Browse::Seek) => {
+            app.failure_message = format!("Nenhuma chave a partir de '{}'.", String::from_utf8_lossy(&key));
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            app.failure_message = format!("Falha ao percorrer o banco de dados: {e}");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+}
+
+// BEGIN, COMMIT e ROLLBACK da transação da sessão
+fn _transaction(app: &mut App) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    let (result, done) = match app.db_command {
+        Some(BEGIN) => (db.begin(), "Transação aberta: as alterações só serão gravadas no COMMIT."),
+        Some(COMMIT) => (db.commit(), "Transação confirmada."),
+        Some(ROLLBACK) => (db.rollback(), "Transação desfeita."),
+        _ => return,
+    };
+    app.refresh_len();
+    match result {
+        Ok(()) => {
+            app.success_message = done.to_string();
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
+        }
+        Err(e) => {
+            app.failure_message = e.to_string();
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+}
+
+// Cria um checkpoint com o nome informado ou, sem nome, lista os existentes
+fn _checkpoint(app: &mut App) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    if app.input.is_empty() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+        app.search_result = db
+            .checkpoints()
+            .iter()
+            .map(|c| format!("{} | {} chaves | {}", c.name, c.key_count, age(now.saturating_sub(c.timestamp))))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if app.search_result.is_empty() {
+            app.search_result = "Nenhum checkpoint criado.".to_string();
+        }
+        app.vertical_scroll = 0;
+        app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView);
+        return;
+    }
+
+    match db.checkpoint(&app.input) {
+        Ok(()) => {
+            app.success_message = format!("Checkpoint '{}' criado.", app.input);
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
+        }
+        Err(e) => {
+            app.failure_message = format!("Falha ao criar o checkpoint: {e}");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+}
+
+// Tempo decorrido desde a criação de um checkpoint
+fn age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("há {seconds} s"),
+        60..3600 => format!("há {} min", seconds / 60),
+        3600..86400 => format!("há {} h", seconds / 3600),
+        _ => format!("há {} dias", seconds / 86400),
+    }
+}
+
+fn _restore(app: &mut App) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    match db.restore(&app.input) {
+        Ok(()) => {
+            app.refresh_len();
+            app.success_message = format!("Banco restaurado para o checkpoint '{}'.", app.input);
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
+        }
+        Err(e) => {
+            app.failure_message = format!("Falha ao restaurar: {e}");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+}
+
+// "create nome", "checkout nome" ou "merge nome"; sem nada, lista as branches
+fn _branch(app: &mut App) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    if app.input.is_empty() {
+        let current = db.current_branch().to_string();
+        app.search_result = db
+            .branches()
+            .iter()
+            .map(|b| {
+                let marker = if b.name == current { "* " } else { "  " };
+                let parent = b.parent.as_ref().map_or_else(String::new, |p| format!(" | criada a partir de {p}"));
+                format!("{marker}{}{parent}", b.name)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if app.search_result.is_empty() {
+            app.search_result = format!("* {current}");
+        }
+        app.vertical_scroll = 0;
+        app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView);
+        return;
+    }
+
+    let (action, name) = app.input.split_once(' ').unwrap_or((&app.input, ""));
+    let result = match action {
+        "create" => db.create_branch(name).map(|_| format!("Branch '{name}' criada.")),
+        "checkout" => db.checkout(name).map(|_| format!("Branch atual: '{name}'.")),
+        // Com conflitos nada é aplicado, então o merge aparece como uma falha
+        "merge" => db.merge(name).and_then(|merge| {
+            if merge.conflicts.is_empty() {
+                return Ok(format!("Merge de '{name}' concluído: {} chaves alteradas.", merge.applied));
+            }
+            Err(Error::other(format!(
+                "{} chaves alteradas nas duas branches ({})",
+                merge.conflicts.len(),
+                merge.conflicts.iter().map(|key| String::from_utf8_lossy(key)).collect::<Vec<_>>().join(", ")
+            )))
+        }),
+        _ => Err(Error::new(ErrorKind::InvalidInput, format!("Ação desconhecida: '{action}'"))),
+    };
+    // O checkout e o merge mudam as chaves visíveis
+    app.refresh_len();
+    match result {
+        Ok(message) => {
+            app.success_message = message;
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
+        }
+        Err(e) => {
+            app.failure_message = format!("Falha na operação com branches: {e}");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+}
+
+fn _compact(app: &mut App) {
+    // A compactação reabre o arquivo e descartaria a transação
+    if app.loaded_db.as_ref().is_some_and(|db| db.in_transaction()) {
+        app.failure_message = "Finalize a transação (COMMIT ou ROLLBACK) antes de compactar.".to_string();
+        app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        return;
+    }
+
+    // O arquivo é substituído pela compactação: fecha o atual e reabre o novo
+    app.loaded_db = None;
+    let name = app.loaded_db_name.clone();
+
+    match compact_database(&name) {
+        Ok(reclaimed) => {
+            app.success_message = format!("Compactação concluída: {reclaimed} bytes recuperados.");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage);
+        }
+        Err(e) => {
+            app.failure_message = format!("Falha na compactação: {e}");
+            app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage);
+        }
+    }
+
+    if let Err(e) = load_database(app, &name) {
+        app.failure_message = format!("Não foi possível reabrir '{name}': {e}");
+        app.current_screen = CurrentScreen::Main(MainMenu::FailureMessage);
+    }
+}
+
 pub fn database_loaded_event_loop(key: KeyEvent, app: &mut App) {
     match app.current_screen {
         CurrentScreen::DatabaseLoaded(DatabasePrompt::SelectCommand) => {
             app.db_command = database_commands(key, app);
             match app.db_command {
                 None => {}
+                Some(COMPACT) => _compact(app),
+                Some(BEGIN | COMMIT | ROLLBACK) => _transaction(app),
                 Some(CLOSE) => {
                     app.loaded_db = None;
-                    app.index = BTree::default();
                     app.current_screen = CurrentScreen::Main(MainMenu::OptionsList);
                     app.option_highlighted = 0;
                 }
@@ -70,6 +492,17 @@ pub fn database_loaded_event_loop(key: KeyEvent, app: &mut App) {
                 }
             }
         }
+        // Comandos que aceitam a entrada vazia (o `user_input` ignora o ENTER sem texto)
+        CurrentScreen::DatabaseLoaded(DatabasePrompt::UserInput)
+            if key.kind == KeyEventKind::Press && key.code == KeyCode::Enter && app.input.is_empty() =>
+        {
+            match app.db_command {
+                Some(BROWSE) => _browse(app, Browse::Seek),
+                Some(CHECKPOINT) => _checkpoint(app),
+                Some(BRANCH) => _branch(app),
+                _ => {}
+            }
+        }
         CurrentScreen::DatabaseLoaded(DatabasePrompt::UserInput)
             if key.kind == KeyEventKind::Press =>
         {
@@ -80,6 +513,12 @@ pub fn database_loaded_event_loop(key: KeyEvent, app: &mut App) {
                 |app| match app.db_command {
                     Some(SEARCH) => _search(app),
                     Some(INSERT) => _insert(app),
+                    Some(DELETE) => _delete(app),
+                    Some(SCAN) => _scan(app, Page::First),
+                    Some(BROWSE) => _browse(app, Browse::Seek),
+                    Some(CHECKPOINT) => _checkpoint(app),
+                    Some(RESTORE) => _restore(app),
+                    Some(BRANCH) => _branch(app),
                     _ => {}
                 },
             );
@@ -87,22 +526,34 @@ pub fn database_loaded_event_loop(key: KeyEvent, app: &mut App) {
         CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage) => match key.code {
             KeyCode::Enter | KeyCode::Esc => {
                 app.input.clear();
+                app.success_message.clear();
+                app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SelectCommand);
+            }
+            _ => {}
+        },
+        CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage) => match key.code {
+            KeyCode::Enter | KeyCode::Esc => {
+                app.input.clear();
+                app.failure_message.clear();
                 app.current_screen = CurrentScreen::DatabaseLoaded(DatabasePrompt::SelectCommand);
             }
             _ => {}
         },
         CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView) => match key.code {
-            KeyCode::Down => {
-                if (app.vertical_scroll as usize) < app.line_count {
-                    app.vertical_scroll = app.vertical_scroll.saturating_add(1);
-                }
+            KeyCode::Down if (app.vertical_scroll as usize) < app.line_count => {
+                app.vertical_scroll = app.vertical_scroll.saturating_add(1);
             }
-            KeyCode::Up => {
-                if app.vertical_scroll > 0 {
-                    app.vertical_scroll = app.vertical_scroll.saturating_sub(1);
-                }
+            KeyCode::Up if app.vertical_scroll > 0 => {
+                app.vertical_scroll = app.vertical_scroll.saturating_sub(1);
             }
+            KeyCode::Right if matches!(app.db_command, Some(SCAN)) => _scan(app, Page::Next),
+            KeyCode::Left if matches!(app.db_command, Some(SCAN)) => _scan(app, Page::Prev),
+            KeyCode::Right if matches!(app.db_command, Some(BROWSE)) => _browse(app, Browse::Next),
+            KeyCode::Left if matches!(app.db_command, Some(BROWSE)) => _browse(app, Browse::Prev),
+            KeyCode::Home if matches!(app.db_command, Some(BROWSE)) => _browse(app, Browse::First),
+            KeyCode::End if matches!(app.db_command, Some(BROWSE)) => _browse(app, Browse::Last),
             KeyCode::Esc => {
+                app.scan_snapshot = None;
                 app.search_result.clear();
                 app.input.clear();
                 app.vertical_scroll = 0;
@@ -1,7 +1,10 @@
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
 
 use crate::app::{App, CurrentScreen, MainMenu};
-use crate::db::create_database;
+use crate::db::{create_database, import_directory};
+use crate::pager::Layout;
 
 use crate::cli::menus::{MainMenuOptions, main_menu};
 use crate::cli::shared::user_input;
@@ -12,7 +15,12 @@ pub fn main_menu_event_loop(key: KeyEvent, app: &mut App) -> bool {
             Some(MainMenuOptions::CreateDb) => {
                 app.current_screen = CurrentScreen::Main(MainMenu::CreateDb)
             }
+            Some(MainMenuOptions::ImportDb) => {
+                app.current_screen = CurrentScreen::Main(MainMenu::ImportDb)
+            }
             Some(MainMenuOptions::LoadDb) => {
+                // Atualiza a quantidade de chaves de cada banco
+                app.fetch_databases();
                 app.option_highlighted = 0;
                 app.current_screen = CurrentScreen::DatabaseList;
             }
@@ -25,15 +33,49 @@ pub fn main_menu_event_loop(key: KeyEvent, app: &mut App) -> bool {
                 app,
                 CurrentScreen::Main(MainMenu::OptionsList),
                 |app| {
-                    create_database(&app.input).expect("Banco de dados deveria ter sido criado");
+                    match create_from_input(&app.input) {
+                        Ok(()) => app.current_screen = CurrentScreen::Main(MainMenu::SuccessMessage),
+                        Err(e) => {
+                            app.failure_message = format!("Não foi possível criar o banco de dados: {e}");
+                            app.current_screen = CurrentScreen::Main(MainMenu::FailureMessage);
+                        }
+                    }
+                    app.input.clear();
+                    app.fetch_databases();
+                },
+            );
+        }
+        CurrentScreen::Main(MainMenu::ImportDb) if key.kind == KeyEventKind::Press => {
+            user_input(
+                key,
+                app,
+                CurrentScreen::Main(MainMenu::OptionsList),
+                |app| {
+                    match import_from_input(&app.input) {
+                        Ok(count) => {
+                            app.success_message = format!("{count} arquivos importados.");
+                            app.current_screen = CurrentScreen::Main(MainMenu::SuccessMessage);
+                        }
+                        Err(e) => {
+                            app.failure_message = format!("Não foi possível importar o diretório: {e}");
+                            app.current_screen = CurrentScreen::Main(MainMenu::FailureMessage);
+                        }
+                    }
                     app.input.clear();
-                    app.current_screen = CurrentScreen::Main(MainMenu::SuccessMessage);
                     app.fetch_databases();
                 },
             );
         }
         CurrentScreen::Main(MainMenu::SuccessMessage) => match key.code {
             KeyCode::Enter | KeyCode::Esc => {
+                app.success_message.clear();
+                app.current_screen = CurrentScreen::Main(MainMenu::OptionsList);
+            }
+            _ => {}
+        },
+        CurrentScreen::Main(MainMenu::FailureMessage) => match key.code {
+            KeyCode::Enter | KeyCode::Esc => {
+                app.failure_message.clear();
                 app.current_screen = CurrentScreen::Main(MainMenu::OptionsList);
             }
             _ => {}
@@ -43,3 +85,40 @@ pub fn main_menu_event_loop(key: KeyEvent, app: &mut App) -> bool {
 
     false
 }
+
+// Entrada no formato "nome [tamanho_da_página] [ordem] [btree|bplus]"; os parâmetros omitidos usam o padrão
+fn create_from_input(input: &str) -> Result<()> {
+    let mut parts = input.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    create_database(name, parse_layout(parts)?)?;
+    Ok(())
+}
+
+// Entrada no formato "nome diretório [tamanho_da_página] [ordem] [btree|bplus]"
+fn import_from_input(input: &str) -> Result<u64> {
+    let mut parts = input.split_whitespace();
+    let (Some(name), Some(dir)) = (parts.next(), parts.next()) else {
+        return Err(Error::new(ErrorKind::InvalidInput, "Informe o nome do banco e o diretório"));
+    };
+
+    import_directory(name, parse_layout(parts)?, Path::new(dir))
+}
+
+// Parâmetros opcionais de criação: "[tamanho_da_página] [ordem] [btree|bplus]"
+fn parse_layout<'a>(mut parts: impl Iterator<Item = &'a str>) -> Result<Layout> {
+    let mut parse = |default: usize| -> Result<usize> {
+        match parts.next() {
+            Some(part) => part
+                .parse()
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Número inválido: {part}"))),
+            None => Ok(default),
+        }
+    };
+    let mut layout = Layout::default();
+    layout.page_size = parse(layout.page_size)?;
+    layout.order = parse(layout.order)?;
+    if let Some(engine) = parts.next() {
+        layout.engine = engine.parse()?;
+    }
+    Ok(layout)
+}
@@ -1,13 +1,14 @@
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use std::io::Result;
 
 use crate::{
     app::{App, DatabaseCommands},
-    btree::BTree,
-    pager::Pager,
+    db::Db,
 };
 
 pub enum MainMenuOptions {
     CreateDb,
+    ImportDb,
     LoadDb,
     Exit,
 }
@@ -16,13 +17,13 @@ pub fn main_menu(key: KeyEvent, app: &mut App) -> Option<MainMenuOptions> {
     match key.code {
         KeyCode::Up => {
             if app.option_highlighted == 0 {
-                app.option_highlighted = 2;
+                app.option_highlighted = 3;
             } else {
                 app.option_highlighted -= 1;
             }
         }
         KeyCode::Down => {
-            if app.option_highlighted == 2 {
+            if app.option_highlighted == 3 {
                 app.option_highlighted = 0;
             } else {
                 app.option_highlighted += 1;
@@ -31,19 +32,21 @@ pub fn main_menu(key: KeyEvent, app: &mut App) -> Option<MainMenuOptions> {
         KeyCode::Enter => {
             match app.option_highlighted {
                 0 => return Some(MainMenuOptions::CreateDb),
-                1 => return Some(MainMenuOptions::LoadDb),
-                2 => return Some(MainMenuOptions::Exit),
+                1 => return Some(MainMenuOptions::ImportDb),
+                2 => return Some(MainMenuOptions::LoadDb),
+                3 => return Some(MainMenuOptions::Exit),
                 _ => return None,
             };
         }
         _ => {}
     }
 
-    return None;
+    None
 }
 
 pub enum DatabaseListingOptions {
     ChooseDb,
+    Failure,
     Exit,
 }
 
@@ -69,19 +72,10 @@ pub fn database_list(key: KeyEvent, app: &mut App) -> Option<DatabaseListingOpti
                 app.option_highlighted = 0;
                 return Some(DatabaseListingOptions::Exit);
             } else {
-                let chosen_db = app.databases[app.option_highlighted as usize].as_str();
-                app.loaded_db = Some(Pager::new(chosen_db));
-
-                let mut pager = Pager::new(chosen_db);
-                match pager.read_at(0, 8) {
-                    Ok(offset) => {
-                        let offset_bytes = offset.try_into()
-                                        .expect("Vetor de bytes contendo offset da raiz deve ter exatamente 8 bytes.");
-                        let root_offset = u64::from_be_bytes(offset_bytes);
-                        app.index = BTree { root: if root_offset == 0 { None } else { Some(root_offset) } };
-                        app.loaded_db = Some(pager);
-                    }
-                    Err(_) => {}
+                let chosen_db = app.databases[app.option_highlighted as usize].clone();
+                if let Err(e) = load_database(app, &chosen_db) {
+                    app.failure_message = format!("Não foi possível abrir '{chosen_db}': {e}");
+                    return Some(DatabaseListingOptions::Failure);
                 }
 
                 return Some(DatabaseListingOptions::ChooseDb);
@@ -91,27 +85,44 @@ pub fn database_list(key: KeyEvent, app: &mut App) -> Option<DatabaseListingOpti
         _ => {}
     }
 
-    return None;
+    None
+}
+
+// Abre o banco de dados (recuperando operações pendentes do log)
+pub fn load_database(app: &mut App, name: &str) -> Result<()> {
+    app.loaded_db = Some(Db::open(name)?);
+    app.loaded_db_name = name.to_string();
+    app.refresh_len();
+    Ok(())
 }
 
 pub fn database_commands(key: KeyEvent, app: &mut App) -> Option<DatabaseCommands> {
     let op = app.option_highlighted;
     match key.code {
         KeyCode::Down => {
-            app.option_highlighted = if op == 3 { 0 } else { op + 1 };
+            app.option_highlighted = if op == 12 { 0 } else { op + 1 };
         }
         KeyCode::Up => {
-            app.option_highlighted = if op == 0 { 3 } else { op - 1 };
+            app.option_highlighted = if op == 0 { 12 } else { op - 1 };
         }
         KeyCode::Enter => match op {
             0 => return Some(DatabaseCommands::SEARCH),
             1 => return Some(DatabaseCommands::INSERT),
             2 => return Some(DatabaseCommands::DELETE),
-            3 => return Some(DatabaseCommands::CLOSE),
+            3 => return Some(DatabaseCommands::SCAN),
+            4 => return Some(DatabaseCommands::BROWSE),
+            5 => return Some(DatabaseCommands::BEGIN),
+            6 => return Some(DatabaseCommands::COMMIT),
+            7 => return Some(DatabaseCommands::ROLLBACK),
+            8 => return Some(DatabaseCommands::CHECKPOINT),
+            9 => return Some(DatabaseCommands::RESTORE),
+            10 => return Some(DatabaseCommands::BRANCH),
+            11 => return Some(DatabaseCommands::COMPACT),
+            12 => return Some(DatabaseCommands::CLOSE),
             _ => {}
         },
         _ => {}
     }
 
-    return None;
+    None
 }
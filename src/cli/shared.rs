@@ -1,9 +1,27 @@
 use crate::app::{App, CurrentScreen};
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
 
+// Bytes por linha do dump de valores binários
+const DUMP_WIDTH: usize = 16;
+
+// Valor exibido na tela: o próprio texto, ou um dump hexadecimal quando não é UTF-8 válido
+pub fn display_bytes(value: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(value) {
+        return text.to_string();
+    }
+
+    let mut dump = format!("Valor binário ({} bytes)\n", value.len());
+    for (i, line) in value.chunks(DUMP_WIDTH).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = line.iter().map(|&b| if b.is_ascii_graphic() { b as char } else { '.' }).collect();
+        dump.push_str(&format!("{:08x}  {:<width$}  {ascii}\n", i * DUMP_WIDTH, hex.join(" "), width = DUMP_WIDTH * 3 - 1));
+    }
+    dump
+}
+
 pub fn user_input<F>(key: KeyEvent, app: &mut App, esc_screen: CurrentScreen, mut callback: F)
 where
-    F: FnMut(&mut App) -> (),
+    F: FnMut(&mut App),
 {
     match key.code {
         KeyCode::Char(value) => {
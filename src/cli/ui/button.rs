@@ -8,8 +8,8 @@ pub struct Button {
 impl Button {
     pub fn new(name: String, highlight: bool) -> Button {
         Button {
-            name: name,
-            highlight: highlight,
+            name,
+            highlight,
         }
     }
 
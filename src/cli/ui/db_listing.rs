@@ -7,7 +7,15 @@ use super::button::Button;
 use crate::App;
 
 pub fn database_listing(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
-    let mut options = app.databases.to_owned();
+    let mut options: Vec<String> = app
+        .databases
+        .iter()
+        .zip(&app.database_lens)
+        .map(|(name, len)| match len {
+            Some(len) => format!("{name} ({len} chaves)"),
+            None => format!("{name} (ilegível)"),
+        })
+        .collect();
     options.push("Voltar".to_string());
 
     // Define layout
@@ -1,24 +1,33 @@
 use ratatui::Frame;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::Paragraph;
 use std::rc::Rc;
 
 use super::button::Button;
 
 use crate::App;
 use crate::app::{CurrentScreen, DatabasePrompt, DatabaseCommands::*};
-use crate::cli::ui::shared::{render_success_message, render_user_input_popup, render_result_view};
+use crate::cli::ui::shared::{render_failure_message, render_success_message, render_user_input_popup, render_result_view};
 
 pub fn database_prompt(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
-    let options: Vec<String> = vec![SEARCH, INSERT, DELETE, CLOSE]
+    let options: Vec<String> = [SEARCH, INSERT, DELETE, SCAN, BROWSE, BEGIN, COMMIT, ROLLBACK, CHECKPOINT, RESTORE, BRANCH, COMPACT, CLOSE]
         .iter()
         .map(|cmd| cmd.to_string())
         .collect();
 
-    // Define layout
+    // Define layout (menu + status line at the bottom)
+    let body = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area[1]);
+
     let options_list = Layout::default()
         .direction(Direction::Vertical)
         .constraints(options.iter().map(|_| Constraint::Length(1)))
-        .split(area[1]);
+        .split(body[0]);
+
+    render_status(frame, app, body[1]);
 
     // Initialize options button list
     let buttons = options
@@ -31,12 +40,41 @@ pub fn database_prompt(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
             Some(INSERT) => {
                 render_user_input_popup(frame, app, "Insira o caminho para o arquivo: ");
             }
+            Some(SEARCH) => {
+                render_user_input_popup(frame, app, "Insira a chave (@checkpoint chave para ler um checkpoint, @@ para uma chave que começa com @): ");
+            }
+            Some(SCAN) => {
+                render_user_input_popup(frame, app, "Insira um prefixo ou um intervalo início..fim (@checkpoint antes para ler um checkpoint, @@ para um início com @): ");
+            }
+            Some(CHECKPOINT) => {
+                render_user_input_popup(frame, app, "Insira o nome do novo checkpoint (vazio para listar): ");
+            }
+            Some(RESTORE) => {
+                render_user_input_popup(frame, app, "Insira o nome do checkpoint a restaurar: ");
+            }
+            Some(BRANCH) => {
+                render_user_input_popup(frame, app, "create nome, checkout nome ou merge nome (vazio para listar): ");
+            }
+            Some(BROWSE) => {
+                render_user_input_popup(frame, app, "Insira a chave inicial ou #posição (vazio para a primeira): ");
+            }
             _ => {
                 render_user_input_popup(frame, app, "Insira a chave: ");
             }
         },
         CurrentScreen::DatabaseLoaded(DatabasePrompt::SuccessMessage) => {
-            render_success_message(frame, "Operação realizada com sucesso!\nAperte ESC ou ENTER para voltar.");
+            if app.success_message.is_empty() {
+                render_success_message(frame, "Operação realizada com sucesso!\nAperte ESC ou ENTER para voltar.");
+            } else {
+                render_success_message(frame, &format!("{}\nAperte ESC ou ENTER para voltar.", app.success_message));
+            }
+        }
+        CurrentScreen::DatabaseLoaded(DatabasePrompt::FailureMessage) => {
+            if app.failure_message.is_empty() {
+                render_failure_message(frame, "Não foi possível realizar a operação.\nAperte ESC ou ENTER para voltar.");
+            } else {
+                render_failure_message(frame, &format!("{}\nAperte ESC ou ENTER para voltar.", app.failure_message));
+            }
         }
         CurrentScreen::DatabaseLoaded(DatabasePrompt::ResultView) => {
             render_result_view(frame, app);
@@ -54,3 +92,20 @@ pub fn database_prompt(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
         frame.render_widget(option_p, options_list[_i]);
     }
 }
+
+// Linha de status com o banco carregado, a quantidade de chaves, as páginas livres e o uso dos caches
+fn render_status(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(db) = &mut app.loaded_db else {
+        return;
+    };
+
+    let len = app.loaded_db_len.map_or_else(|| "?".to_string(), |len| len.to_string());
+    let pages = db.cache_stats();
+    let nodes = db.node_cache_stats();
+    let transaction = if db.in_transaction() { " (transação aberta)" } else { "" };
+    let status = format!(
+        "{} [{}]{transaction} | {len} chaves | {} páginas livres | cache de páginas: {} acertos, {} faltas | cache de nós: {} acertos, {} faltas",
+        app.loaded_db_name, db.current_branch(), db.free_page_count(), pages.hits, pages.misses, nodes.hits, nodes.misses
+    );
+    frame.render_widget(Paragraph::new(status).style(Style::default().fg(Color::DarkGray)), area);
+}
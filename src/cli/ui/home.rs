@@ -7,7 +7,7 @@ use super::button::Button;
 use crate::App;
 use crate::app::MainMenu;
 use crate::app::{CurrentScreen, DatabaseCommands, DatabasePrompt};
-use crate::cli::ui::shared::{render_user_input_popup, render_success_message};
+use crate::cli::ui::shared::{render_failure_message, render_user_input_popup, render_success_message};
 
 
 pub fn home(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
@@ -18,12 +18,14 @@ pub fn home(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
             Constraint::Length(1),
             Constraint::Length(1),
             Constraint::Length(1),
+            Constraint::Length(1),
         ])
         .split(area[1]);
 
     // Initialize options button list
-    let mut options = vec![
+    let mut options = [
         Button::new("Criar banco de dados".to_string(), false),
+        Button::new("Importar diretório".to_string(), false),
         Button::new("Carregar banco de dados".to_string(), false),
         Button::new("Sair".to_string(), false),
     ];
@@ -31,7 +33,10 @@ pub fn home(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
     // Check if popup is activated
     match app.current_screen {
         CurrentScreen::Main(MainMenu::CreateDb) => {
-            render_user_input_popup(frame, app, "Insira o nome do banco de dados");
+            render_user_input_popup(frame, app, "Insira o nome do banco de dados [tamanho da página] [ordem] [btree|bplus]");
+        }
+        CurrentScreen::Main(MainMenu::ImportDb) => {
+            render_user_input_popup(frame, app, "Insira o nome do banco de dados, o diretório [tamanho da página] [ordem] [btree|bplus]");
         }
         CurrentScreen::DatabaseLoaded(DatabasePrompt::UserInput) => match app.db_command {
             Some(DatabaseCommands::INSERT) => {
@@ -42,7 +47,14 @@ pub fn home(frame: &mut Frame, app: &mut App, area: Rc<[Rect]>) {
             }
         },
         CurrentScreen::Main(MainMenu::SuccessMessage) => {
-            render_success_message(frame, "Novo banco de dados criado com sucesso!\nAperte ESC ou ENTER para voltar.");
+            if app.success_message.is_empty() {
+                render_success_message(frame, "Novo banco de dados criado com sucesso!\nAperte ESC ou ENTER para voltar.");
+            } else {
+                render_success_message(frame, &format!("{}\nAperte ESC ou ENTER para voltar.", app.success_message));
+            }
+        }
+        CurrentScreen::Main(MainMenu::FailureMessage) => {
+            render_failure_message(frame, &format!("{}\nAperte ESC ou ENTER para voltar.", app.failure_message));
         }
         _ => {}
     }
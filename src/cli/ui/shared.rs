@@ -6,6 +6,7 @@ use ratatui::text::Text;
 use ratatui::widgets::{Block, Borders, Padding, Paragraph, Scrollbar, Wrap};
 
 use crate::App;
+use crate::app::DatabaseCommands;
 
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 pub fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
@@ -72,6 +73,24 @@ pub fn render_success_message(frame: &mut Frame, message: &str) {
     frame.render_widget(message, popup);
 }
 
+pub fn render_failure_message(frame: &mut Frame, message: &str) {
+    let popup = centered_rect(40, 5, frame.area());
+
+    let message_block = Block::default()
+        .borders(Borders::NONE)
+        .style(Style::default().bg(Color::LightRed))
+        .padding(Padding::uniform(1));
+
+    let message = Paragraph::new(
+        Text::from(message).style(Style::default().bg(Color::LightRed).fg(Color::Black)),
+    )
+    .centered()
+    .wrap(Wrap { trim: true })
+    .block(message_block);
+
+    frame.render_widget(message, popup);
+}
+
 pub fn render_result_view(frame: &mut Frame, app: &mut App) {
     let popup = centered_rect(50, 20, frame.area());
 
@@ -84,8 +103,21 @@ pub fn render_result_view(frame: &mut Frame, app: &mut App) {
         .borders(Borders::ALL)
         .style(Style::default().bg(Color::LightGreen))
         .padding(Padding::uniform(1))
-        .title(format!(" Arquivo {}", app.input))
-        .title_bottom(" Pressione ESC para voltar");
+        .title(match app.db_command {
+            Some(DatabaseCommands::SCAN) => format!(" SCAN {} (página {})", app.input, app.scan_pages.len()),
+            Some(DatabaseCommands::BROWSE) => match app.browse_position {
+                Some((rank, len)) => format!(" {} ({} de {len})", app.input, rank + 1),
+                None => format!(" {}", app.input),
+            },
+            Some(DatabaseCommands::CHECKPOINT) => " Checkpoints".to_string(),
+            Some(DatabaseCommands::BRANCH) => " Branches".to_string(),
+            _ => format!(" Arquivo {}", app.input),
+        })
+        .title_bottom(match app.db_command {
+            Some(DatabaseCommands::SCAN) => " ←/→ página anterior/seguinte, ESC para voltar",
+            Some(DatabaseCommands::BROWSE) => " ←/→ entrada anterior/seguinte, Home/End primeira/última, ESC para voltar",
+            _ => " Pressione ESC para voltar",
+        });
 
     let text = Text::from(app.search_result.clone())
         .style(Style::default().bg(Color::LightGreen).fg(Color::Black));
@@ -0,0 +1,480 @@
+use std::io::Result;
+use std::ops::{Bound, RangeBounds};
+
+use crate::btree::Value;
+use crate::node_page::{self, NodePage};
+use crate::pager::Pager;
+
+// Cursor que percorre a árvore em ordem de chave, nos dois sentidos. A posição fica sempre
+// entre duas entradas: `next` devolve a entrada seguinte e avança, `prev` devolve a anterior
+// e recua. O caminho desde a raiz fica em uma pilha de (offset da página, índice): nos nós
+// internos o índice é o filho por onde a descida passou; na folha, a posição entre as chaves.
+// As páginas são lidas direto do paginador (e do seu cache), sem decodificar os nós.
+// Na B+Tree com as folhas ligadas (ver `Node::linked`) o avanço passa de uma folha para a
+// seguinte pela ligação, sem voltar aos ancestrais; a pilha fica só com a folha até que
+// `prev` precise subir de novo
+pub struct Cursor<'a> {
+    pager: &'a mut Pager,
+    root: Option<u64>,
+    linked: bool,
+    stack: Vec<(u64, usize)>,
+}
+
+impl<'a> Cursor<'a> {
+    // Cursor posicionado antes da primeira entrada da árvore com a raiz indicada
+    pub fn new(root: Option<u64>, pager: &'a mut Pager) -> Result<Self> {
+        let linked = match root {
+            Some(root) => NodePage::new(pager.page(root)?, root)?.linked(),
+            None => false,
+        };
+        let mut cursor = Cursor { pager, root, linked, stack: Vec::new() };
+        cursor.seek_first()?;
+        Ok(cursor)
+    }
+
+    // Posiciona antes da primeira entrada
+    pub fn seek_first(&mut self) -> Result<()> {
+        self.stack.clear();
+        match self.root {
+            Some(root) => self.descend(root, false),
+            None => Ok(()),
+        }
+    }
+
+    // Posiciona depois da última entrada (não se chama `last` para não ser confundido com
+    // `Iterator::last`, que consome o cursor)
+    pub fn seek_last(&mut self) -> Result<()> {
+        self.stack.clear();
+        match self.root {
+            Some(root) => self.descend(root, true),
+            None => Ok(()),
+        }
+    }
+
+    // Posiciona antes da primeira entrada com chave maior ou igual a `key`
+    pub fn seek(&mut self, key: impl AsRef<[u8]>) -> Result<()> {
+        let key = key.as_ref();
+        self.stack.clear();
+        let Some(mut offset) = self.root else {
+            return Ok(());
+        };
+
+        loop {
+            let (page, found) = node_page::search(self.pager, offset, key)?;
+            if page.is_leaf() {
+                self.stack.push((offset, found.unwrap_or_else(|i| i)));
+                return Ok(());
+            }
+
+            match found {
+                // Entrada de um nó interno da B-Tree: a posição logo antes dela é o fim da subárvore da esquerda
+                Ok(i) if page.has_values() => {
+                    let child = page.child(i)?;
+                    self.stack.push((offset, i));
+                    return self.descend(child, true);
+                }
+                // Separador da B+Tree: a chave, se existir, abre a subárvore da direita
+                Ok(i) => {
+                    self.stack.push((offset, i + 1));
+                    offset = page.child(i + 1)?;
+                }
+                Err(i) => {
+                    self.stack.push((offset, i));
+                    offset = page.child(i)?;
+                }
+            }
+        }
+    }
+
+    // Devolve a entrada anterior à posição e recua; None no início da árvore
+    pub fn prev(&mut self) -> Option<Result<(Vec<u8>, Vec<u8>)>> {
+        self.backward().and_then(|entry| self.read(entry)).transpose()
+    }
+
+    // Desce pela borda esquerda (ou direita) da subárvore até a folha
+    fn descend(&mut self, mut offset: u64, rightmost: bool) -> Result<()> {
+        loop {
+            let page = NodePage::new(self.pager.page(offset)?, offset)?;
+            let i = if rightmost { page.key_count() } else { 0 };
+            self.stack.push((offset, i));
+            if page.is_leaf() {
+                return Ok(());
+            }
+            offset = page.child(i)?;
+        }
+    }
+
+    // Entrada `i` do nó na página `offset`; o valor só é lido (ver `read`) quando for devolvido
+    fn entry(&mut self, offset: u64, i: usize) -> Result<(Vec<u8>, Value)> {
+        let (key, value) = {
+            let page = NodePage::new(self.pager.page(offset)?, offset)?;
+            (page.key(i)?, page.value(i)?)
+        };
+        Ok((key.read(self.pager)?, value))
+    }
+
+    fn read(&mut self, entry: Option<(Vec<u8>, Value)>) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match entry {
+            Some((key, value)) => Ok(Some((key, value.read(self.pager)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn forward(&mut self) -> Result<Option<(Vec<u8>, Value)>> {
+        loop {
+            let Some(&(leaf, i)) = self.stack.last() else {
+                return Ok(None);
+            };
+            let page = NodePage::new(self.pager.page(leaf)?, leaf)?;
+            let (key_count, next) = (page.key_count(), page.next()?);
+            if i < key_count {
+                self.stack.last_mut().unwrap().1 += 1;
+                return self.entry(leaf, i).map(Some);
+            }
+
+            // Fim da folha em uma árvore ligada: segue direto para a folha seguinte
+            if self.linked {
+                let Some(next) = next else {
+                    return Ok(None);
+                };
+                self.stack.clear();
+                self.stack.push((next, 0));
+                continue;
+            }
+
+            // Fim da folha: sobe até o ancestral mais próximo que ainda tem chaves à direita.
+            // Se não houver, o cursor fica no fim, sem alterar a posição
+            let mut depth = None;
+            for (d, &(offset, i)) in self.stack.iter().enumerate().rev().skip(1) {
+                if i < NodePage::new(self.pager.page(offset)?, offset)?.key_count() {
+                    depth = Some(d);
+                    break;
+                }
+            }
+            let Some(depth) = depth else {
+                return Ok(None);
+            };
+
+            self.stack.truncate(depth + 1);
+            let (offset, i) = self.stack[depth];
+            self.stack[depth].1 = i + 1;
+            let page = NodePage::new(self.pager.page(offset)?, offset)?;
+            let (child, has_values) = (page.child(i + 1)?, page.has_values());
+            self.descend(child, false)?;
+            // Na B+Tree o separador não é uma entrada: a próxima está no início da folha seguinte
+            if has_values {
+                return self.entry(offset, i).map(Some);
+            }
+        }
+    }
+
+    fn backward(&mut self) -> Result<Option<(Vec<u8>, Value)>> {
+        loop {
+            let Some(&(leaf, i)) = self.stack.last() else {
+                return Ok(None);
+            };
+            if i > 0 {
+                self.stack.last_mut().unwrap().1 -= 1;
+                return self.entry(leaf, i - 1).map(Some);
+            }
+
+            // Depois de seguir uma ligação a pilha guarda só a folha: o caminho desde a raiz é
+            // refeito pela primeira chave dela, que leva à mesma folha
+            if self.stack.len() == 1 && self.root != Some(leaf) {
+                let key = NodePage::new(self.pager.page(leaf)?, leaf)?.key(0)?.read(self.pager)?;
+                self.seek(key)?;
+            }
+
+            // Início da folha: sobe até o ancestral mais próximo que ainda tem chaves à esquerda
+            let last = self.stack.len() - 1;
+            let Some(depth) = self.stack[..last].iter().rposition(|&(_, i)| i > 0) else {
+                return Ok(None);
+            };
+
+            self.stack.truncate(depth + 1);
+            let (offset, i) = self.stack[depth];
+            self.stack[depth].1 = i - 1;
+            let page = NodePage::new(self.pager.page(offset)?, offset)?;
+            let (child, has_values) = (page.child(i - 1)?, page.has_values());
+            self.descend(child, true)?;
+            if has_values {
+                return self.entry(offset, i - 1).map(Some);
+            }
+        }
+    }
+}
+
+// Percorre as entradas em ordem crescente de chave, a partir da posição atual
+impl Iterator for Cursor<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.forward().and_then(|entry| self.read(entry)).transpose()
+    }
+}
+
+// Entradas de um intervalo de chaves (ou com um prefixo) em ordem crescente. O cursor desce
+// direto até a primeira chave do intervalo e a iteração para na primeira chave fora dele,
+// sem ler o seu valor
+pub struct Range<'a> {
+    cursor: Cursor<'a>,
+    end: Bound<Vec<u8>>,
+    prefix: Vec<u8>,
+    done: bool,
+}
+
+impl<'a> Range<'a> {
+    pub fn new<R: RangeBounds<Vec<u8>>>(range: R, root: Option<u64>, pager: &'a mut Pager) -> Result<Self> {
+        let mut cursor = Cursor::new(root, pager)?;
+        let mut range_start = None;
+        match range.start_bound() {
+            Bound::Included(start) => cursor.seek(start)?,
+            Bound::Excluded(start) => {
+                cursor.seek(start)?;
+                range_start = Some(start.clone());
+            }
+            Bound::Unbounded => {}
+        }
+
+        let mut range = Range { cursor, end: range.end_bound().cloned(), prefix: Vec::new(), done: false };
+        // Início exclusivo: a própria chave, se existir, fica de fora
+        if let Some(start) = range_start {
+            range.skip_key(&start)?;
+        }
+        Ok(range)
+    }
+
+    // Todas as chaves que começam com `prefix`
+    pub fn prefix(prefix: &[u8], root: Option<u64>, pager: &'a mut Pager) -> Result<Self> {
+        let mut range = Range::new(prefix.to_vec().., root, pager)?;
+        range.prefix = prefix.to_vec();
+        Ok(range)
+    }
+
+    // Recomeça a iteração na primeira chave maior ou igual a `key` (que deve estar no intervalo),
+    // mantendo o fim do intervalo; usado na paginação
+    pub fn seek(&mut self, key: impl AsRef<[u8]>) -> Result<()> {
+        self.done = false;
+        self.cursor.seek(key)
+    }
+
+    // Pula a chave `key` se ela for a próxima
+    fn skip_key(&mut self, key: &[u8]) -> Result<()> {
+        if let Some((next, _)) = self.cursor.forward()?
+            && next != key
+        {
+            self.cursor.backward()?;
+        }
+        Ok(())
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        let before_end = match &self.end {
+            Bound::Included(end) => key <= end.as_slice(),
+            Bound::Excluded(end) => key < end.as_slice(),
+            Bound::Unbounded => true,
+        };
+        before_end && key.starts_with(&self.prefix)
+    }
+}
+
+impl Iterator for Range<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let entry = match self.cursor.forward() {
+            Ok(Some((key, _))) if !self.contains(&key) => None,
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        self.done = entry.is_none();
+        self.cursor.read(entry).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::{BTree, Engine};
+    use crate::db::create_database;
+    use crate::pager::{Layout, Pager};
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    #[test]
+    fn test_cursor_walks_both_engines_in_order() {
+        use rand::Rng;
+
+        let name = "test_cursor";
+        let filename = format!("./databases/{name}.kvdb");
+        for engine in [Engine::BTree, Engine::BPlusTree] {
+            let _ = fs::remove_file(&filename);
+            create_database(name, Layout { order: 3, engine, ..Default::default() }).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let mut tree = BTree::default();
+
+            // Árvore vazia
+            let mut cursor = tree.cursor(&mut pager).unwrap();
+            assert!(cursor.next().is_none());
+            assert!(cursor.prev().is_none());
+
+            let mut model = BTreeMap::new();
+            let mut rng = rand::rng();
+            for _ in 0..1500 {
+                let k = format!("{:04}", rng.random_range(0..600) * 2).into_bytes();
+                if rng.random_bool(0.7) {
+                    let v = k.repeat(if rng.random_bool(0.05) { 500 } else { 1 });
+                    tree.insert(k.clone(), v.clone(), &mut pager).unwrap();
+                    model.insert(k, v);
+                } else {
+                    tree.delete(k.clone(), &mut pager).unwrap();
+                    model.remove(&k);
+                }
+            }
+
+            let entries: Vec<(Vec<u8>, Vec<u8>)> = tree.cursor(&mut pager).unwrap().map(Result::unwrap).collect();
+            assert!(entries.iter().map(|(k, v)| (k, v)).eq(model.iter()), "{engine:?}");
+
+            // De trás para frente
+            let mut cursor = tree.cursor(&mut pager).unwrap();
+            cursor.seek_last().unwrap();
+            let mut backwards = Vec::new();
+            while let Some(entry) = cursor.prev() {
+                backwards.push(entry.unwrap());
+            }
+            backwards.reverse();
+            assert_eq!(backwards, entries);
+
+            // seek para chaves existentes (pares) e ausentes (ímpares), alternando os sentidos
+            for probe in [0, 1, 17, 600, 601, 1198, 1199, 1300] {
+                let key = format!("{probe:04}").into_bytes();
+                cursor.seek(&key).unwrap();
+                let after = model.range(key.clone()..).next().map(|(k, _)| k.clone());
+                let before = model.range(..key.clone()).next_back().map(|(k, _)| k.clone());
+
+                assert_eq!(cursor.next().map(|e| e.unwrap().0), after, "{engine:?} {key:?}");
+                if after.is_some() {
+                    assert_eq!(cursor.prev().map(|e| e.unwrap().0), after);
+                }
+                assert_eq!(cursor.prev().map(|e| e.unwrap().0), before, "{engine:?} {key:?}");
+            }
+
+            // Nas pontas o cursor não sai do lugar
+            cursor.seek_first().unwrap();
+            assert!(cursor.prev().is_none());
+            assert_eq!(cursor.next().unwrap().unwrap().0, *model.keys().next().unwrap());
+        }
+
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_cursor_follows_leaf_links() {
+        use crate::node_page::NodePage;
+
+        let name = "test_leaf_links";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_database(name, Layout { order: 8, engine: Engine::BPlusTree, ..Default::default() }).unwrap();
+        let mut pager = Pager::new(name).unwrap();
+        let model: BTreeMap<Vec<u8>, Vec<u8>> =
+            (0..3000).map(|i| (format!("chave:{i:05}").into_bytes(), format!("{i}").into_bytes())).collect();
+        let mut tree = BTree::bulk_load(model.clone(), &mut pager).unwrap();
+
+        // Seguindo as ligações a partir da primeira folha, as chaves aparecem em ordem
+        let mut offset = tree.root.unwrap();
+        let mut depth = 1;
+        while !NodePage::new(pager.page(offset).unwrap(), offset).unwrap().is_leaf() {
+            offset = NodePage::new(pager.page(offset).unwrap(), offset).unwrap().child(0).unwrap();
+            depth += 1;
+        }
+        let (mut keys, mut leaves) = (Vec::new(), 0);
+        loop {
+            let page = NodePage::new(pager.page(offset).unwrap(), offset).unwrap();
+            keys.extend((0..page.key_count()).map(|i| page.key(i).unwrap()));
+            leaves += 1;
+            match page.next().unwrap() {
+                Some(next) => offset = next,
+                None => break,
+            }
+        }
+        assert!(depth > 2);
+        assert!(keys.iter().map(|key| key.read(&mut pager).unwrap()).eq(model.keys().cloned()));
+
+        // O cursor lê cada folha uma vez e, dos nós internos, só o caminho até a primeira folha
+        let mut pager = Pager::new(name).unwrap();
+        assert_eq!(tree.cursor(&mut pager).unwrap().count(), model.len());
+        assert_eq!(pager.cache_stats().misses, leaves + depth - 1);
+
+        // Um intervalo também passa de folha em folha, e o recuo refaz o caminho pelos ancestrais
+        let range = tree.range(b"chave:01000".to_vec()..b"chave:02000".to_vec(), &mut pager).unwrap();
+        assert_eq!(range.count(), 1000);
+        let mut cursor = tree.cursor(&mut pager).unwrap();
+        let forward: Vec<_> = cursor.by_ref().take(1500).map(|e| e.unwrap().0).collect();
+        let mut backward = Vec::new();
+        while let Some(entry) = cursor.prev() {
+            backward.push(entry.unwrap().0);
+        }
+        backward.reverse();
+        assert_eq!(backward, forward);
+
+        // Uma escrita grava a folha em outra página e a raiz nova deixa de ser ligada; o cursor
+        // volta a passar pelos ancestrais
+        tree.insert(b"chave:01500x".to_vec(), b"nova".to_vec(), &mut pager).unwrap();
+        let root = tree.root.unwrap();
+        assert!(!NodePage::new(pager.page(root).unwrap(), root).unwrap().linked());
+        assert_eq!(tree.cursor(&mut pager).unwrap().count(), model.len() + 1);
+
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_range_and_prefix_scans() {
+        use std::ops::Bound;
+
+        let name = "test_range_scan";
+        let filename = format!("./databases/{name}.kvdb");
+        for engine in [Engine::BTree, Engine::BPlusTree] {
+            let _ = fs::remove_file(&filename);
+            create_database(name, Layout { engine, ..Default::default() }).unwrap();
+            let mut pager = Pager::new(name).unwrap();
+            let mut tree = BTree::default();
+            for table in ["order", "product", "user"] {
+                for i in 0..1000 {
+                    tree.insert(format!("{table}:{i:04}").into_bytes(), format!("{i}").into_bytes(), &mut pager).unwrap();
+                }
+            }
+
+            let keys = |range: super::Range| range.map(|e| String::from_utf8(e.unwrap().0).unwrap()).collect::<Vec<_>>();
+            let range = tree.range(b"product:0100".to_vec()..b"product:0105".to_vec(), &mut pager).unwrap();
+            assert_eq!(keys(range), (100..105).map(|i| format!("product:{i:04}")).collect::<Vec<_>>());
+
+            let bounds = (Bound::Excluded(b"order:0997".to_vec()), Bound::Included(b"product:0001".to_vec()));
+            let range = tree.range(bounds, &mut pager).unwrap();
+            assert_eq!(keys(range), ["order:0998", "order:0999", "product:0000", "product:0001"]);
+
+            let range = tree.range(b"user:0998".to_vec().., &mut pager).unwrap();
+            assert_eq!(keys(range), ["user:0998", "user:0999"]);
+            assert!(tree.scan_prefix(b"customer:", &mut pager).unwrap().next().is_none());
+
+            // O prefixo é lido a partir da sua primeira chave, sem percorrer a árvore inteira
+            let mut pager = Pager::new(name).unwrap();
+            let mut scan = tree.scan_prefix(b"user:05", &mut pager).unwrap();
+            let (first, value) = scan.next().unwrap().unwrap();
+            assert_eq!((first.as_slice(), value.as_slice()), (&b"user:0500"[..], &b"500"[..]));
+            assert_eq!(scan.count(), 99);
+            let pages = fs::metadata(&filename).unwrap().len() / pager.layout().page_size as u64;
+            assert!(pager.cache_stats().misses < pages / 4, "{:?} de {pages} páginas", pager.cache_stats());
+
+            // Paginação: recomeça em uma chave do intervalo mantendo o fim
+            let mut scan = tree.scan_prefix(b"order:", &mut pager).unwrap();
+            scan.seek("order:0990").unwrap();
+            assert_eq!(scan.count(), 10);
+        }
+
+        fs::remove_file(&filename).unwrap();
+    }
+}
@@ -1,6 +1,530 @@
-use std::fs::{File, OpenOptions, create_dir};
-use std::io::{Result, Write};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, create_dir};
+use std::io::{Error, ErrorKind, Result, Write};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::batch::WriteBatch;
+use crate::btree::{BTree, Transaction};
+use crate::cache::CacheStats;
+use crate::cursor::Cursor;
+use crate::pager::{Backend, Branch, Checkpoint, DEFAULT_CACHE_CAPACITY, Durability, Layout, Pager};
+use crate::snapshot::Snapshot;
+use crate::wal::{Wal, WalRecord};
+
+// Opções de abertura de um banco de dados
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    pub durability: Durability,
+    // Páginas mantidas no cache do paginador
+    pub cache_capacity: usize,
+    pub backend: Backend,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        DbOptions {
+            durability: Durability::default(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            backend: Backend::default(),
+        }
+    }
+}
+
+impl DbOptions {
+    // Opções definidas pelo ambiente de execução
+    // (ex.: KVDB_DURABILITY=none|commit|page, KVDB_CACHE_PAGES=1024, KVDB_BACKEND=file|mmap)
+    pub fn from_env() -> Result<Self> {
+        let mut options = DbOptions::default();
+        if let Ok(durability) = std::env::var("KVDB_DURABILITY") {
+            options.durability = durability.parse()?;
+        }
+        if let Ok(capacity) = std::env::var("KVDB_CACHE_PAGES") {
+            options.cache_capacity = capacity.parse().map_err(|_| {
+                Error::new(ErrorKind::InvalidInput, format!("Capacidade de cache inválida: {capacity}"))
+            })?;
+        }
+        if let Ok(backend) = std::env::var("KVDB_BACKEND") {
+            options.backend = backend.parse()?;
+        }
+        Ok(options)
+    }
+}
+
+// Resultado de `Db::merge`
+#[derive(Debug, Default, PartialEq)]
+pub struct Merge {
+    // Quantidade de chaves alteradas na branch atual
+    pub applied: usize,
+    // Chaves alteradas de formas diferentes nas duas branches; quando há alguma, nada é aplicado
+    pub conflicts: Vec<Vec<u8>>,
+}
+
+// Banco de dados aberto: B-Tree, paginador e log de escrita antecipada
+pub struct Db {
+    name: String,
+    pager: Pager,
+    tree: BTree,
+    wal: Wal,
+    // Transação aberta por `begin` e as operações feitas nela, registradas no log só no `commit`
+    transaction: Option<(Transaction, WriteBatch)>,
+}
+
+impl Db {
+    // Abre o banco de dados com as opções definidas pelo ambiente
+    pub fn open(name: &str) -> Result<Self> {
+        Db::open_with(name, DbOptions::from_env()?)
+    }
+
+    // Abre o banco de dados e refaz as operações do log que não chegaram a ser publicadas
+    pub fn open_with(name: &str, options: DbOptions) -> Result<Self> {
+        let mut pager = Pager::new(name)?;
+        pager.set_cache_capacity(options.cache_capacity);
+        pager.set_backend(options.backend)?;
+        let tree = BTree::new(pager.root_offset());
+        let wal = Wal::open(name, pager.wal_seq())?;
+
+        let mut db = Db { name: name.to_string(), pager, tree, wal, transaction: None };
+        db.set_durability(options.durability);
+        db.recover()?;
+        Ok(db)
+    }
+
+    // Vale tanto para o log quanto para as páginas e o cabeçalho
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.pager.set_durability(durability);
+        self.wal.set_durability(durability);
+    }
+
+    // As chaves e os valores são bytes; `String` e `&str` também são aceitos diretamente
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Result<()> {
+        let (key, value) = (key.into(), value.into());
+        if self.transaction.is_some() {
+            let mut batch = WriteBatch::new();
+            batch.put(key, value);
+            return self.stage(batch);
+        }
+        self.log_and_apply(WalRecord::Insert { key, value })
+    }
+
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) -> Result<()> {
+        let key = key.into();
+        if self.transaction.is_some() {
+            let mut batch = WriteBatch::new();
+            batch.delete(key);
+            return self.stage(batch);
+        }
+        self.log_and_apply(WalRecord::Delete { key })
+    }
+
+    // Aplica o lote como uma unidade: ou todas as operações aparecem no banco, ou nenhuma
+    pub fn write(&mut self, batch: WriteBatch) -> Result<()> {
+        if self.transaction.is_some() {
+            return self.stage(batch);
+        }
+        self.log_and_apply(WalRecord::Batch(batch))
+    }
+
+    // Abre uma transação: as escritas seguintes montam uma raiz privada, visível nas leituras
+    // deste `Db`, e só chegam ao arquivo (e ao log) no `commit`
+    pub fn begin(&mut self) -> Result<()> {
+        if self.transaction.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Já existe uma transação aberta"));
+        }
+        self.transaction = Some((self.tree.begin(&self.pager), WriteBatch::new()));
+        Ok(())
+    }
+
+    // Registra as operações da transação no log como um lote e publica a raiz privada
+    pub fn commit(&mut self) -> Result<()> {
+        let (transaction, batch) = self.transaction.take().ok_or_else(no_transaction)?;
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let result = match self.wal.append(&WalRecord::Batch(batch)) {
+            Ok(seq) => {
+                self.pager.set_wal_seq(seq);
+                self.tree.commit(transaction, &mut self.pager)
+            }
+            Err(e) => {
+                self.tree.rollback(transaction, &mut self.pager);
+                Err(e)
+            }
+        };
+        self.wal.reset()?;
+        result
+    }
+
+    // Descarta a transação; nada dela chegou ao arquivo
+    pub fn rollback(&mut self) -> Result<()> {
+        let (transaction, _) = self.transaction.take().ok_or_else(no_transaction)?;
+        self.tree.rollback(transaction, &mut self.pager);
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub fn search(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.tree.search(key.as_ref(), &mut self.pager)
+    }
+
+    pub fn cursor(&mut self) -> Result<Cursor<'_>> {
+        self.tree.cursor(&mut self.pager)
+    }
+
+    pub fn len(&mut self) -> Result<u64> {
+        self.tree.len(&mut self.pager)
+    }
+
+    // Visão somente leitura fixada na raiz atual (a privada, se houver uma transação aberta),
+    // que não enxerga as escritas feitas depois dela
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        self.snapshot_at(self.tree.root)
+    }
+
+    fn snapshot_at(&self, root: Option<u64>) -> Result<Snapshot> {
+        let pager = Pager::new(&self.name)?;
+        Ok(Snapshot::new(pager, root, self.pager.pin()))
+    }
+
+    pub fn rank(&mut self, key: impl AsRef<[u8]>) -> Result<u64> {
+        self.tree.rank(key.as_ref(), &mut self.pager)
+    }
+
+    pub fn select(&mut self, k: u64) -> Result<Option<Vec<u8>>> {
+        self.tree.select(k, &mut self.pager)
+    }
+
+    // Preserva a raiz publicada com um nome: ela continua legível (`open_checkpoint`) e pode voltar a
+    // ser a raiz atual (`restore`) mesmo depois de novas escritas
+    pub fn checkpoint(&mut self, name: &str) -> Result<()> {
+        if name.is_empty() || self.checkpoints().iter().any(|c| c.name == name) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Nome de checkpoint inválido ou repetido: '{name}'")));
+        }
+        if self.transaction.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Finalize a transação antes de criar um checkpoint"));
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+        let checkpoint = Checkpoint {
+            name: name.to_string(),
+            root_offset: self.tree.root,
+            key_count: self.pager.key_count(),
+            timestamp,
+        };
+        // Se a publicação falhar, o checkpoint sai da tabela em memória junto com a cópia não publicada
+        let saved = self.pager.save_refs();
+        let result = self
+            .pager
+            .add_checkpoint(checkpoint)
+            .and_then(|_| self.pager.update_root_offset(&self.tree.root.unwrap_or(0).to_be_bytes()));
+        if result.is_err() {
+            self.pager.discard_pending();
+            self.pager.restore_refs(saved);
+        }
+        result
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        self.pager.checkpoints()
+    }
+
+    // Visão somente leitura do banco como estava no checkpoint
+    pub fn open_checkpoint(&self, name: &str) -> Result<Snapshot> {
+        let checkpoint = self.find_checkpoint(name)?;
+        self.snapshot_at(checkpoint.root_offset)
+    }
+
+    // Publica a raiz do checkpoint como raiz atual. As páginas da árvore descartada que nenhum
+    // checkpoint (nem snapshot aberto) alcança voltam para a lista livre
+    pub fn restore(&mut self, name: &str) -> Result<()> {
+        if self.transaction.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Finalize a transação antes de restaurar um checkpoint"));
+        }
+        let checkpoint = self.find_checkpoint(name)?.clone();
+
+        // Se a publicação falhar, a árvore volta para a raiz atual
+        let transaction = self.tree.begin(&self.pager);
+        for page in self.tree.pages(&mut self.pager)? {
+            self.pager.free(page);
+        }
+        self.tree.root = checkpoint.root_offset;
+        self.pager.set_key_count(checkpoint.key_count);
+        self.tree.commit(transaction, &mut self.pager)
+    }
+
+    fn find_checkpoint(&self, name: &str) -> Result<&Checkpoint> {
+        self.checkpoints()
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Checkpoint '{name}' não encontrado")))
+    }
+
+    pub fn branches(&self) -> &[Branch] {
+        self.pager.branches()
+    }
+
+    pub fn current_branch(&self) -> &str {
+        self.pager.current_branch()
+    }
+
+    // Cria uma branch a partir da raiz atual. Ela recebe escritas independentes depois do
+    // `checkout`, e a raiz de origem fica guardada como base para o `merge`
+    pub fn create_branch(&mut self, name: &str) -> Result<()> {
+        if name.is_empty() || name == self.current_branch() || self.branches().iter().any(|b| b.name == name) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Nome de branch inválido ou repetido: '{name}'")));
+        }
+        if self.transaction.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Finalize a transação antes de criar uma branch"));
+        }
+
+        let current = self.current_branch().to_string();
+        let transaction = self.tree.begin(&self.pager);
+        let mut branches = self.branches().to_vec();
+        // A branch original só entra na tabela quando a primeira outra é criada
+        if branches.is_empty() {
+            branches.push(Branch {
+                name: current.clone(),
+                root_offset: self.tree.root,
+                key_count: self.pager.key_count(),
+                parent: None,
+                base_offset: None,
+                base_seq: 0,
+            });
+        }
+        branches.push(Branch {
+            name: name.to_string(),
+            root_offset: self.tree.root,
+            key_count: self.pager.key_count(),
+            parent: Some(current.clone()),
+            base_offset: self.tree.root,
+            base_seq: next_base_seq(&branches),
+        });
+        self.publish_branches(branches, &current, transaction, None)
+    }
+
+    // Troca a branch atual: a raiz da branch deixada é guardada na tabela e a da outra é publicada
+    pub fn checkout(&mut self, name: &str) -> Result<()> {
+        if self.transaction.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Finalize a transação antes de trocar de branch"));
+        }
+        let current = self.current_branch().to_string();
+        if name == current {
+            return Ok(());
+        }
+        let target = self.find_branch(name)?.clone();
+
+        let mut branches = self.branches().to_vec();
+        if let Some(branch) = branches.iter_mut().find(|b| b.name == current) {
+            branch.root_offset = self.tree.root;
+            branch.key_count = self.pager.key_count();
+        }
+
+        let transaction = self.tree.begin(&self.pager);
+        self.tree.root = target.root_offset;
+        self.pager.set_key_count(target.key_count);
+        self.publish_branches(branches, name, transaction, None)
+    }
+
+    // Aplica na branch atual as alterações feitas na outra desde a base das duas. Chaves alteradas
+    // de formas diferentes dos dois lados são devolvidas como conflitos, e nesse caso nada é aplicado.
+    // Depois do merge a base passa a ser a raiz da branch de origem, então um novo merge só traz o
+    // que mudou desde este
+    pub fn merge(&mut self, name: &str) -> Result<Merge> {
+        if self.transaction.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Finalize a transação antes de fazer um merge"));
+        }
+        let current = self.current_branch().to_string();
+        if name == current {
+            return Err(Error::new(ErrorKind::InvalidInput, "Não é possível fazer o merge de uma branch nela mesma"));
+        }
+        let source = self.find_branch(name)?.clone();
+
+        let fork = self.merge_base(&current, name)?;
+        let (theirs, ours) = {
+            let mut base = self.snapshot_at(fork.base_offset)?;
+            let theirs = base.diff(&mut self.snapshot_at(source.root_offset)?)?;
+            let ours = base.diff(&mut self.snapshot()?)?;
+            (theirs, ours)
+        };
+
+        let conflicts: Vec<Vec<u8>> = theirs
+            .iter()
+            .filter(|(key, value)| ours.get(*key).is_some_and(|ours| ours != *value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if !conflicts.is_empty() {
+            return Ok(Merge { applied: 0, conflicts });
+        }
+
+        let mut batch = WriteBatch::new();
+        for (key, value) in theirs {
+            if ours.get(&key) == Some(&value) {
+                continue;
+            }
+            match value {
+                Some(value) => batch.put(key, value),
+                None => batch.delete(key),
+            };
+        }
+        let applied = batch.len();
+
+        // As alterações são feitas em uma transação, publicada junto com a tabela de branches em vez
+        // de passar pelo `commit`: ou o merge aparece inteiro no arquivo, ou nada dele
+        self.begin()?;
+        if let Err(e) = self.write(batch) {
+            self.rollback()?;
+            return Err(e);
+        }
+        let (transaction, _) = self.transaction.take().ok_or_else(no_transaction)?;
+
+        // Quando uma das branches foi criada diretamente a partir da outra, a base dela avança e as
+        // páginas que só a base antiga alcançava voltam para a lista livre
+        let mut branches = self.branches().to_vec();
+        let base_seq = next_base_seq(&branches);
+        let direct = match fork.parent.as_deref() {
+            Some(parent) => fork.name == current && parent == name || fork.name == name && parent == current,
+            None => false,
+        };
+        if direct
+            && let Some(branch) = branches.iter_mut().find(|b| b.name == fork.name)
+        {
+            branch.base_offset = source.root_offset;
+            branch.base_seq = base_seq;
+        }
+        let released = fork.base_offset.filter(|_| direct && fork.base_offset != source.root_offset);
+        self.publish_branches(branches, &current, transaction, released)?;
+        Ok(Merge { applied, conflicts })
+    }
+
+    // Publica a tabela de branches junto com a raiz atual, liberando as páginas que só a raiz `released`
+    // alcançava. Se a publicação falhar, a árvore volta para a raiz do início da transação e a tabela
+    // anterior volta a valer
+    fn publish_branches(&mut self, branches: Vec<Branch>, current: &str, transaction: Transaction, released: Option<u64>) -> Result<()> {
+        let saved = self.pager.save_refs();
+        let result = self
+            .pager
+            .set_branches(branches, current)
+            .and_then(|_| match released {
+                Some(root) => self.pager.release_tree(root, self.tree.root),
+                None => Ok(()),
+            })
+            .and_then(|_| self.pager.update_root_offset(&self.tree.root.unwrap_or(0).to_be_bytes()));
+        if result.is_err() {
+            self.tree.rollback(transaction, &mut self.pager);
+            self.pager.restore_refs(saved);
+        }
+        result
+    }
+
+    // Branch cuja base serve de base para o merge entre `ours` e `theirs`. As duas cadeias de branches
+    // mães são percorridas até o ancestral comum mais próximo; de cada lado, a separação é a base da
+    // branch filha do ancestral nessa cadeia (ou nenhuma, do lado que é o próprio ancestral). Das duas,
+    // vale a mais antiga, que as duas branches contêm
+    fn merge_base(&self, ours: &str, theirs: &str) -> Result<Branch> {
+        let (ours, theirs) = (self.lineage(ours)?, self.lineage(theirs)?);
+        let (i, j) = ours
+            .iter()
+            .enumerate()
+            .find_map(|(i, branch)| theirs.iter().position(|b| b.name == branch.name).map(|j| (i, j)))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "As branches não têm um ancestral comum"))?;
+
+        let forks = [i.checked_sub(1).map(|i| &ours[i]), j.checked_sub(1).map(|j| &theirs[j])];
+        forks
+            .into_iter()
+            .flatten()
+            .min_by_key(|fork| fork.base_seq)
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Não é possível fazer o merge de uma branch nela mesma"))
+    }
+
+    // A branch seguida das branches mães, até a original
+    fn lineage(&self, name: &str) -> Result<Vec<Branch>> {
+        let mut lineage = vec![self.find_branch(name)?.clone()];
+        while let Some(parent) = lineage.last().and_then(|b| b.parent.clone()) {
+            if lineage.len() > self.branches().len() {
+                return Err(Error::new(ErrorKind::InvalidData, "Ciclo na tabela de branches"));
+            }
+            lineage.push(self.find_branch(&parent)?.clone());
+        }
+        Ok(lineage)
+    }
+
+    fn find_branch(&self, name: &str) -> Result<&Branch> {
+        self.branches()
+            .iter()
+            .find(|b| b.name == name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Branch '{name}' não encontrada")))
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.pager.cache_stats()
+    }
+
+    pub fn node_cache_stats(&self) -> CacheStats {
+        self.pager.node_cache_stats()
+    }
+
+    pub fn free_page_count(&self) -> usize {
+        self.pager.free_page_count()
+    }
+
+    fn recover(&mut self) -> Result<()> {
+        for (seq, record) in self.wal.read_records()? {
+            if seq <= self.pager.wal_seq() {
+                continue;
+            }
+
+            match self.apply(seq, record) {
+                // Operação que já havia sido recusada antes da queda: não há o que refazer
+                Err(e) if e.kind() == ErrorKind::InvalidInput => {}
+                result => result?,
+            }
+        }
+        self.wal.reset()
+    }
+
+    // Dentro da transação as operações só são registradas no log no `commit`
+    fn stage(&mut self, batch: WriteBatch) -> Result<()> {
+        let Some((_, staged)) = &mut self.transaction else {
+            return Err(no_transaction());
+        };
+        // Um lote que falha é desfeito e não faz parte da transação
+        self.tree.stage(batch.clone(), &mut self.pager)?;
+        staged.append(batch);
+        Ok(())
+    }
+
+    // O registro vai para o log (e para o disco) antes de tocar na árvore
+    fn log_and_apply(&mut self, record: WalRecord) -> Result<()> {
+        let seq = self.wal.append(&record)?;
+        let result = self.apply(seq, record);
+        self.wal.reset()?;
+        result
+    }
+
+    // A sequência é publicada no cabeçalho junto com a nova raiz
+    fn apply(&mut self, seq: u64, record: WalRecord) -> Result<()> {
+        self.pager.set_wal_seq(seq);
+        match record {
+            WalRecord::Insert { key, value } => self.tree.insert(key, value, &mut self.pager),
+            WalRecord::Delete { key } => self.tree.delete(key, &mut self.pager),
+            WalRecord::Batch(batch) => self.tree.write(batch, &mut self.pager),
+        }
+    }
+}
+
+// Próxima ordem de base (ver `Branch::base_seq`)
+fn next_base_seq(branches: &[Branch]) -> u64 {
+    branches.iter().map(|b| b.base_seq + 1).max().unwrap_or(0)
+}
+
+fn no_transaction() -> Error {
+    Error::new(ErrorKind::InvalidInput, "Nenhuma transação aberta")
+}
 
 pub fn list_databases() -> Vec<String> {
     let pathstr = "./databases";
@@ -10,50 +534,813 @@ pub fn list_databases() -> Vec<String> {
     }
 
     let mut dbs: Vec<String> = Vec::new();
-    for db in path.read_dir().expect("Error reading database directory") {
-        if let Ok(entry) = db {
-            let p = entry.path().to_str().unwrap().to_owned();
-            dbs.push(p[pathstr.len() + 1..p.len() - 5].to_string());
+    for entry in path.read_dir().expect("Error reading database directory").flatten() {
+        // Ignora arquivos auxiliares (ex.: cópias temporárias da compactação)
+        if entry.path().extension().is_none_or(|ext| ext != "kvdb") {
+            continue;
         }
+        let p = entry.path().to_str().unwrap().to_owned();
+        dbs.push(p[pathstr.len() + 1..p.len() - 5].to_string());
     }
 
     dbs
 }
 
-pub fn create_database(name: &str) -> Result<File> {
-    assert_eq!(name.is_empty(), false, "Database name must be non-empty.");
+// Quantidade de chaves publicadas no banco de dados, sem refazer o log (usada na listagem)
+pub fn database_len(name: &str) -> Result<u64> {
+    let mut pager = Pager::new(name)?;
+    BTree::new(pager.root_offset()).len(&mut pager)
+}
+
+// Cria o arquivo do banco com o tamanho de página, a ordem e a organização da árvore
+// escolhidos (ver `Layout::default`); os três ficam gravados no cabeçalho
+pub fn create_database(name: &str, layout: Layout) -> Result<File> {
+    check_name(name)?;
+    Pager::check_layout(&layout)?;
 
     let pathstr = &format!("./databases/{name}.kvdb");
     let path = Path::new(pathstr);
 
     let parent = path.parent().unwrap();
     if !parent.exists() {
-        match create_dir(parent) {
-            Ok(..) => println!("Databases folder created."),
-            Err(e) => panic!("Error creating database folder: {e:?}"),
+        create_dir(parent).map_err(|e| Error::new(e.kind(), format!("Falha ao criar a pasta dos bancos de dados: {e}")))?;
+    }
+
+    let mut db = File::create_new(path).map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => Error::new(ErrorKind::AlreadyExists, format!("O banco '{name}' já existe")),
+        _ => Error::new(e.kind(), format!("Falha ao criar o arquivo do banco de dados: {e}")),
+    })?;
+
+    db.write_all(&Pager::new_header(&layout))?; // Árvore e lista de páginas livres vazias na criação
+    Ok(db)
+}
+
+// Cria o banco de dados já carregado com as entradas, em ordem crescente de chave (ver
+// `BTree::bulk_load`). Se a carga falhar, o arquivo criado é removido. Retorna a quantidade de chaves
+pub fn bulk_load_database<I>(name: &str, layout: Layout, entries: I) -> Result<u64>
+where
+    I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+{
+    // Um banco existente nunca é sobrescrito nem removido pela carga: a criação falha antes
+    let filename = format!("./databases/{name}.kvdb");
+    create_database(name, layout)?;
+    let result = Pager::new(name).and_then(|mut pager| {
+        BTree::bulk_load(entries, &mut pager)?;
+        Ok(pager.key_count())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&filename);
+    }
+    result
+}
+
+// Importa os arquivos de um diretório para um banco novo, como o INSERT faria com cada um:
+// o nome do arquivo (sem extensão) é a chave e o conteúdo é o valor. Os nomes são ordenados
+// antes da carga; os conteúdos são lidos um a um, durante a carga
+pub fn import_directory(name: &str, layout: Layout, dir: &Path) -> Result<u64> {
+    let mut files = Vec::new();
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
         }
+        let key = path.file_prefix().and_then(|prefix| prefix.to_str()).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("Nome de arquivo inválido: {}", path.display()))
+        })?;
+        files.push((key.as_bytes().to_vec(), path));
     }
+    files.sort();
 
-    let mut db = match File::create_new(path) {
-        Ok(f) => f,
+    // A carga para no primeiro arquivo que não puder ser lido
+    let mut error = None;
+    let entries = files.into_iter().map_while(|(key, path)| match fs::read(&path) {
+        Ok(value) => Some((key, value)),
         Err(e) => {
-            panic!("Error creating database file: {e:?}")
+            error = Some(Error::new(e.kind(), format!("{}: {e}", path.display())));
+            None
         }
-    };
+    });
+    let result = bulk_load_database(name, layout, entries);
 
-    db.write_all(&[0u8;8])?; // B-Tree root offset on creation is 8
-    return Ok(db);
+    match error {
+        Some(e) => {
+            let _ = fs::remove_file(format!("./databases/{name}.kvdb"));
+            Err(e)
+        }
+        None => result,
+    }
+}
+
+// O nome vem da TUI: um nome vazio é uma entrada inválida, não um erro de programação
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "O nome do banco de dados não pode ser vazio"));
+    }
+    Ok(())
 }
 
-pub fn open_database(name: &str) -> File {
-    assert_eq!(name.is_empty(), false, "Database name must be non-empty.");
+// O cabeçalho do arquivo aberto é validado por `Pager::from_file`
+pub fn open_database(name: &str) -> Result<File> {
+    check_name(name)?;
 
     let pathstr = &format!("./databases/{name}.kvdb");
     let path = Path::new(pathstr);
 
-    return OpenOptions::new()
+    OpenOptions::new()
         .write(true)
         .read(true)
         .open(path)
-        .expect("Não foi possível abrir o arquivo do banco de dados");
+}
+
+// Reescreve o banco de dados apenas com os nós alcançáveis a partir da raiz (e das raízes dos
+// checkpoints e das branches) e substitui o arquivo original de forma atômica. Snapshots abertos continuam lendo
+// o arquivo antigo, que só desaparece quando o último deles for descartado.
+// Retorna a quantidade de bytes recuperados.
+pub fn compact_database(name: &str) -> Result<u64> {
+    check_name(name)?;
+
+    let pathstr = &format!("./databases/{name}.kvdb");
+    let path = Path::new(pathstr);
+    let original_size = path.metadata()?.len();
+
+    let mut pager = Pager::new(name)?;
+    let tree = BTree::new(pager.root_offset());
+
+    // O novo arquivo é montado ao lado do original para que o rename seja atômico
+    let mut compacted = tempfile::Builder::new()
+        .prefix(&format!(".{name}"))
+        .suffix(".compact")
+        .tempfile_in(path.parent().unwrap())?;
+    compacted.as_file_mut().write_all(&Pager::new_header(&pager.layout()))?;
+
+    let mut target = Pager::from_file(compacted.reopen()?)?;
+    let mut copied = HashMap::new();
+    let root_offset = tree.copy_to(&mut pager, &mut target, &mut copied)?;
+    for checkpoint in pager.checkpoints().to_vec() {
+        let root_offset = BTree::new(checkpoint.root_offset).copy_to(&mut pager, &mut target, &mut copied)?;
+        target.add_checkpoint(Checkpoint { root_offset, ..checkpoint })?;
+    }
+    let current = pager.current_branch().to_string();
+    let mut branches = Vec::new();
+    for branch in pager.branches().to_vec() {
+        // A entrada da branch atual só é atualizada no checkout; a raiz dela é a do cabeçalho
+        let (branch_root, key_count) = if branch.name == current {
+            (root_offset, pager.key_count())
+        } else {
+            (BTree::new(branch.root_offset).copy_to(&mut pager, &mut target, &mut copied)?, branch.key_count)
+        };
+        let base_offset = match branch.parent {
+            Some(_) => BTree::new(branch.base_offset).copy_to(&mut pager, &mut target, &mut copied)?,
+            None => None,
+        };
+        branches.push(Branch { root_offset: branch_root, key_count, base_offset, ..branch });
+    }
+    target.set_branches(branches, &current)?;
+    target.set_key_count(pager.key_count());
+    target.set_wal_seq(pager.wal_seq());
+    target.update_root_offset(&root_offset.unwrap_or(0).to_be_bytes())?;
+
+    compacted.as_file().sync_all()?;
+    let compacted_size = compacted.as_file().metadata()?.len();
+    compacted.persist(path).map_err(|e| e.error)?;
+
+    Ok(original_size.saturating_sub(compacted_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{Engine, Node, MAX_KEY_SIZE};
+    use crate::header::SLOT_SIZE;
+    use std::fs::{self, create_dir_all};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_compact_database() {
+        let name = "test_compact";
+        let filename = format!("./databases/{name}.kvdb");
+        if Path::new(&filename).exists() {
+            fs::remove_file(&filename).unwrap();
+        }
+        create_database(name, Layout::default()).unwrap();
+
+        let mut pager = Pager::new(name).unwrap();
+        let mut tree = BTree::default();
+        for i in 0..200 {
+            let k = format!("{:03}", i).into_bytes();
+            tree.insert(k.clone(), k.repeat(500), &mut pager).unwrap();
+        }
+        for i in (0..200).filter(|i| i % 4 != 0) {
+            tree.delete(format!("{:03}", i).into_bytes(), &mut pager).unwrap();
+        }
+        drop(pager);
+
+        let size_before = fs::metadata(&filename).unwrap().len();
+        let reclaimed = compact_database(name).unwrap();
+        let size_after = fs::metadata(&filename).unwrap().len();
+        assert!(reclaimed > 0);
+        assert_eq!(size_before - size_after, reclaimed);
+
+        let mut pager = Pager::new(name).unwrap();
+        let tree = BTree::new(pager.root_offset());
+        assert_eq!(pager.key_count(), 50);
+        for i in 0..200 {
+            let k = format!("{:03}", i).into_bytes();
+            let expected = if i % 4 == 0 { Some(k.repeat(500)) } else { None };
+            assert_eq!(tree.search(&k, &mut pager).unwrap(), expected);
+        }
+
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects_foreign_files() {
+        let name = "test_foreign";
+        let filename = format!("./databases/{name}.kvdb");
+        create_dir_all("./databases").unwrap();
+
+        fs::write(&filename, b"isto definitivamente nao e um banco kvdb").unwrap();
+        let err = Pager::new(name).err().expect("Arquivo estrangeiro deveria ser recusado");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // Mesmo arquivo kvdb, mas com uma versão de formato desconhecida nas duas cópias do cabeçalho
+        let mut header = Pager::new_header(&Layout::default());
+        for slot in [0, SLOT_SIZE] {
+            header[slot + 8..slot + 12].copy_from_slice(&u32::MAX.to_be_bytes());
+        }
+        fs::write(&filename, header).unwrap();
+        let err = Pager::new(name).err().expect("Versão desconhecida deveria ser recusada");
+        assert!(err.to_string().contains("Versão de formato"));
+
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_torn_header_write_keeps_previous_commit() {
+        let name = "test_torn_header";
+        let filename = format!("./databases/{name}.kvdb");
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+
+        let mut pager = Pager::new(name).unwrap();
+        let mut tree = BTree::new(pager.root_offset());
+        tree.insert(b"a".to_vec(), b"1".to_vec(), &mut pager).unwrap();
+        tree.insert(b"b".to_vec(), b"2".to_vec(), &mut pager).unwrap();
+        drop(pager);
+
+        // Publicação n é gravada na cópia n % 2
+        let pager_slot = |seq: usize| seq % 2 * SLOT_SIZE;
+
+        // Simula uma escrita interrompida na cópia do cabeçalho da última publicação
+        let newest = pager_slot(2);
+        let mut data = fs::read(&filename).unwrap();
+        data[newest + 30] ^= 0xFF;
+        fs::write(&filename, data).unwrap();
+
+        let mut pager = Pager::new(name).unwrap();
+        let tree = BTree::new(pager.root_offset());
+        assert_eq!(tree.search(b"a", &mut pager).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(tree.search(b"b", &mut pager).unwrap(), None);
+        assert_eq!(pager.key_count(), 1);
+
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_durability_modes() {
+        let name = "test_durability";
+        create_dir_all("./databases").unwrap();
+
+        for durability in [Durability::None, Durability::Commit, Durability::Page] {
+            let _ = fs::remove_file(format!("./databases/{name}.kvdb"));
+            create_database(name, Layout::default()).unwrap();
+
+            let mut db = Db::open_with(name, DbOptions { durability, ..Default::default() }).unwrap();
+            for i in 0..20 {
+                db.insert(format!("k{i}"), format!("v{i}")).unwrap();
+            }
+            db.delete("k3".to_string()).unwrap();
+            drop(db);
+
+            let mut db = Db::open_with(name, DbOptions { durability, ..Default::default() }).unwrap();
+            assert_eq!(db.search("k7").unwrap(), Some(b"v7".to_vec()), "{durability:?}");
+            assert_eq!(db.search("k3").unwrap(), None, "{durability:?}");
+        }
+
+        assert!("fsync".parse::<Durability>().is_err());
+        fs::remove_file(format!("./databases/{name}.kvdb")).unwrap();
+        fs::remove_file(format!("./databases/{name}.wal")).unwrap();
+    }
+
+    #[test]
+    fn test_custom_page_size_and_order() {
+        let name = "test_custom_layout";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+
+        assert!(create_database(name, Layout { page_size: 5000, ..Default::default() }).is_err());
+        assert!(create_database(name, Layout { order: 1, ..Default::default() }).is_err());
+        assert!(!Path::new(&filename).exists());
+        assert_eq!(create_database("", Layout::default()).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(open_database("").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(compact_database("").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let layout = Layout { page_size: 16384, order: 20, engine: Engine::BPlusTree };
+        create_database(name, layout).unwrap();
+        // Um nome repetido é um erro, não um pânico, e o arquivo existente fica intacto
+        assert_eq!(create_database(name, Layout::default()).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        let mut pager = Pager::new(name).unwrap();
+        assert_eq!(pager.layout(), layout);
+
+        let mut tree = BTree::new(pager.root_offset());
+        for i in 0..500 {
+            tree.insert(format!("{:04}", i).into_bytes(), b"x".repeat(i % 700), &mut pager).unwrap();
+        }
+        // Com até 39 chaves por nó, 500 chaves exigem mais de um nível
+        assert!(!Node::load(tree.root.unwrap(), &mut pager).unwrap().children.is_empty());
+        assert_eq!(fs::metadata(&filename).unwrap().len() % 16384, 0);
+        drop(pager);
+
+        // A compactação preserva o layout do arquivo original
+        compact_database(name).unwrap();
+        let mut pager = Pager::new(name).unwrap();
+        assert_eq!(pager.layout(), layout);
+        let tree = BTree::new(pager.root_offset());
+        for i in 0..500 {
+            let value = tree.search(format!("{:04}", i).as_bytes(), &mut pager).unwrap();
+            assert_eq!(value, Some(b"x".repeat(i % 700)));
+        }
+
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_import_directory() {
+        let name = "test_import";
+        let filename = format!("./databases/{name}.kvdb");
+        let dir = Path::new("./databases/test_import_dir");
+        let _ = fs::remove_file(&filename);
+        let _ = fs::remove_dir_all(dir);
+        create_dir_all(dir.join("subdir")).unwrap();
+
+        for i in (0..300).rev() {
+            fs::write(dir.join(format!("{i:03}.txt")), "x".repeat(i * 7)).unwrap();
+        }
+        assert_eq!(import_directory(name, Layout::default(), dir).unwrap(), 300);
+        let mut pager = Pager::new(name).unwrap();
+        let tree = BTree::new(pager.root_offset());
+        assert_eq!(tree.len(&mut pager).unwrap(), 300);
+        for i in 0..300 {
+            assert_eq!(tree.search(format!("{i:03}").as_bytes(), &mut pager).unwrap(), Some(b"x".repeat(i * 7)));
+        }
+        drop(pager);
+
+        // O banco já existe; e dois arquivos com a mesma chave não deixam um banco pela metade
+        assert!(import_directory(name, Layout::default(), dir).is_err());
+        fs::remove_file(&filename).unwrap();
+        fs::write(dir.join("000.md"), "y").unwrap();
+        assert!(import_directory(name, Layout::default(), dir).is_err());
+        assert!(!Path::new(&filename).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_transactions() {
+        let name = "test_transactions";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+
+        let mut db = Db::open(name).unwrap();
+        for i in 0..300 {
+            db.insert(format!("{i:03}"), "v".repeat(i * 3)).unwrap();
+        }
+        assert!(db.commit().is_err());
+        assert!(db.rollback().is_err());
+
+        // As alterações aparecem nas leituras da transação, mas não no arquivo
+        db.begin().unwrap();
+        assert!(db.begin().is_err());
+        for i in 0..150 {
+            db.delete(format!("{i:03}")).unwrap();
+        }
+        db.insert("novo".to_string(), "x".repeat(3000)).unwrap();
+        // Uma operação recusada não desfaz as anteriores da transação
+        assert!(db.insert("k".repeat(MAX_KEY_SIZE + 1), String::new()).is_err());
+        assert_eq!(db.len().unwrap(), 151);
+        assert_eq!(db.search("000").unwrap(), None);
+        assert_eq!(Db::open(name).unwrap().len().unwrap(), 300);
+
+        db.rollback().unwrap();
+        assert!(!db.in_transaction());
+        assert_eq!(db.len().unwrap(), 300);
+        assert_eq!(db.search("novo").unwrap(), None);
+        for i in 0..300 {
+            assert_eq!(db.search(format!("{i:03}")).unwrap(), Some(b"v".repeat(i * 3)));
+        }
+
+        // O commit publica tudo de uma vez
+        db.begin().unwrap();
+        for i in 0..150 {
+            db.delete(format!("{i:03}")).unwrap();
+        }
+        let mut batch = WriteBatch::new();
+        batch.put("novo".to_string(), "x".repeat(3000)).put("299".to_string(), "y".to_string());
+        db.write(batch).unwrap();
+        db.commit().unwrap();
+        drop(db);
+
+        let mut db = Db::open(name).unwrap();
+        assert_eq!(db.len().unwrap(), 151);
+        assert_eq!(db.search("100").unwrap(), None);
+        assert_eq!(db.search("200").unwrap(), Some(b"v".repeat(600)));
+        assert_eq!(db.search("299").unwrap().as_deref(), Some(&b"y"[..]));
+        assert_eq!(db.search("novo").unwrap(), Some(b"x".repeat(3000)));
+        drop(db);
+
+        // O arquivo continua consistente depois da compactação
+        compact_database(name).unwrap();
+        let mut db = Db::open(name).unwrap();
+        assert_eq!(db.len().unwrap(), 151);
+        drop(db);
+
+        fs::remove_file(&filename).unwrap();
+        let _ = fs::remove_file(format!("./databases/{name}.wal"));
+    }
+
+    #[test]
+    fn test_snapshots_keep_their_pages() {
+        let name = "test_snapshots";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+
+        let original: Vec<(Vec<u8>, Vec<u8>)> = (0..120).map(|i| (format!("{i:03}").into_bytes(), b"v".repeat(i * 17))).collect();
+        let options = DbOptions { durability: Durability::None, ..Default::default() };
+        let mut db = Db::open_with(name, options).unwrap();
+        for (k, v) in &original {
+            db.insert(k.clone(), v.clone()).unwrap();
+        }
+        let churn = |db: &mut Db, round: usize| {
+            for (k, _) in &original {
+                db.insert(k.clone(), format!("{round}").repeat(700)).unwrap();
+            }
+        };
+
+        // Sem snapshots, as páginas liberadas são reaproveitadas e o arquivo para de crescer
+        churn(&mut db, 0);
+        let size = fs::metadata(&filename).unwrap().len();
+        churn(&mut db, 1);
+        assert!(fs::metadata(&filename).unwrap().len() < size * 5 / 4);
+
+        let mut snapshot = db.snapshot().unwrap();
+        let mut older = db.snapshot().unwrap();
+        db.begin().unwrap();
+        db.delete("000".to_string()).unwrap();
+        // O snapshot de uma transação enxerga a raiz privada
+        let mut private = db.snapshot().unwrap();
+        db.commit().unwrap();
+        for round in 2..6 {
+            churn(&mut db, round);
+        }
+        db.delete("119".to_string()).unwrap();
+
+        // Com os snapshots abertos, nenhuma página alcançável por eles foi reaproveitada
+        let read_all = |snapshot: &mut Snapshot| snapshot.range(..).unwrap().collect::<Result<Vec<_>>>().unwrap();
+        let expected: Vec<_> = original.iter().map(|(k, _)| (k.clone(), b"1".repeat(700))).collect();
+        assert_eq!(read_all(&mut snapshot), expected);
+        assert_eq!(read_all(&mut private), expected[1..]);
+        assert_eq!(snapshot.scan_prefix("11").unwrap().count(), 10);
+        assert!(fs::metadata(&filename).unwrap().len() > size * 2);
+
+        // Enquanto um snapshot antigo existir, as páginas liberadas continuam retidas
+        drop(snapshot);
+        drop(private);
+        churn(&mut db, 6);
+        assert_eq!(read_all(&mut older), expected);
+        drop(older);
+
+        // Sem snapshots, as páginas retidas voltam para a lista livre
+        churn(&mut db, 7);
+        let size = fs::metadata(&filename).unwrap().len();
+        for round in 8..12 {
+            churn(&mut db, round);
+        }
+        assert_eq!(fs::metadata(&filename).unwrap().len(), size);
+
+        // A compactação troca o arquivo, mas o snapshot continua lendo o arquivo que abriu
+        let mut snapshot = db.snapshot().unwrap();
+        db.delete("001".to_string()).unwrap();
+        drop(db);
+        compact_database(name).unwrap();
+        assert_eq!(read_all(&mut snapshot).len(), 120);
+        assert_eq!(Db::open(name).unwrap().len().unwrap(), 119);
+
+        fs::remove_file(&filename).unwrap();
+        let _ = fs::remove_file(format!("./databases/{name}.wal"));
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let name = "test_checkpoints";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+        let options = DbOptions { durability: Durability::None, ..Default::default() };
+
+        let original: Vec<(Vec<u8>, Vec<u8>)> = (0..120).map(|i| (format!("{i:03}").into_bytes(), b"v".repeat(i * 17))).collect();
+        let mut db = Db::open_with(name, options.clone()).unwrap();
+        for (k, v) in &original {
+            db.insert(k.clone(), v.clone()).unwrap();
+        }
+        db.checkpoint("antes").unwrap();
+        assert!(db.checkpoint("antes").is_err());
+        assert!(db.open_checkpoint("depois").is_err());
+
+        // Um checkpoint que não chegou ao arquivo também não fica na tabela em memória
+        db.pager.set_file(fs::File::open(&filename).unwrap());
+        assert!(db.checkpoint("perdido").is_err());
+        db.pager.set_file(fs::File::options().read(true).write(true).open(&filename).unwrap());
+        assert_eq!(db.checkpoints().len(), 1);
+        assert!(db.open_checkpoint("perdido").is_err());
+
+        let churn = |db: &mut Db, round: usize| {
+            for (k, _) in original.iter().step_by(2) {
+                db.insert(k.clone(), format!("{round}").repeat(700)).unwrap();
+            }
+            db.delete(format!("{:03}", round + 1)).unwrap();
+        };
+        let read_all = |snapshot: &mut Snapshot| snapshot.range(..).unwrap().collect::<Result<Vec<_>>>().unwrap();
+
+        churn(&mut db, 0);
+        db.checkpoint("depois").unwrap();
+        churn(&mut db, 1);
+        drop(db);
+
+        // A tabela é persistida e as páginas dos checkpoints não são reaproveitadas depois de reabrir
+        let mut db = Db::open_with(name, options.clone()).unwrap();
+        let names: Vec<_> = db.checkpoints().iter().map(|c| c.name.clone()).collect();
+        assert_eq!(names, ["antes", "depois"]);
+        churn(&mut db, 2);
+        let size = fs::metadata(&filename).unwrap().len();
+        for round in 3..8 {
+            churn(&mut db, round);
+        }
+        // As páginas que não pertencem a nenhum checkpoint continuam sendo reaproveitadas
+        assert!(fs::metadata(&filename).unwrap().len() < size * 5 / 4);
+
+        assert_eq!(read_all(&mut db.open_checkpoint("antes").unwrap()), original);
+        let mut after = db.open_checkpoint("depois").unwrap();
+        assert_eq!(after.range(..).unwrap().count(), 119);
+        assert_eq!(after.search("000").unwrap(), Some(b"0".repeat(700)));
+        assert_eq!(after.search("001").unwrap(), None);
+        assert_eq!(after.search("003").unwrap(), Some(b"v".repeat(3 * 17)));
+        drop(after);
+
+        // Restaurar traz de volta a raiz e a contagem do checkpoint; o banco segue aceitando escritas
+        db.restore("antes").unwrap();
+        assert_eq!(db.len().unwrap(), 120);
+        assert_eq!(db.cursor().unwrap().collect::<Result<Vec<_>>>().unwrap(), original);
+        churn(&mut db, 8);
+        assert_eq!(read_all(&mut db.open_checkpoint("antes").unwrap()), original);
+        drop(db);
+
+        // A compactação copia as árvores dos checkpoints uma única vez onde elas se sobrepõem
+        compact_database(name).unwrap();
+        let compacted = fs::metadata(&filename).unwrap().len();
+        let mut db = Db::open_with(name, options).unwrap();
+        assert_eq!(db.checkpoints().len(), 2);
+        assert_eq!(read_all(&mut db.open_checkpoint("antes").unwrap()), original);
+        assert_eq!(db.open_checkpoint("depois").unwrap().search("003").unwrap(), Some(b"v".repeat(3 * 17)));
+        db.restore("antes").unwrap();
+        drop(db);
+        compact_database(name).unwrap();
+        assert!(fs::metadata(&filename).unwrap().len() < compacted);
+
+        fs::remove_file(&filename).unwrap();
+        let _ = fs::remove_file(format!("./databases/{name}.wal"));
+    }
+
+    // Páginas do arquivo (fora a do cabeçalho) que nenhuma raiz alcança e que não estão na lista livre:
+    // só as que guardam a própria lista e a tabela de raízes, a menos que alguma tenha se perdido
+    fn unaccounted_pages(db: &mut Db) -> usize {
+        let mut roots = vec![db.tree.root];
+        for branch in db.branches().to_vec() {
+            if branch.name != db.current_branch() {
+                roots.push(branch.root_offset);
+            }
+            roots.push(branch.base_offset);
+        }
+        let mut reachable = std::collections::HashSet::new();
+        for root in roots {
+            reachable.extend(BTree::new(root).pages(&mut db.pager).unwrap());
+        }
+        let pages = fs::metadata(format!("./databases/{}.kvdb", db.name)).unwrap().len() as usize / Layout::default().page_size;
+        pages - 1 - reachable.len() - db.free_page_count()
+    }
+
+    #[test]
+    fn test_branches() {
+        let name = "test_branches";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+        let options = DbOptions { durability: Durability::None, ..Default::default() };
+
+        let mut db = Db::open_with(name, options.clone()).unwrap();
+        for i in 0..120 {
+            db.insert(format!("{i:03}"), "v".repeat(i * 17)).unwrap();
+        }
+        assert_eq!(db.current_branch(), "main");
+        db.create_branch("feature").unwrap();
+        assert!(db.create_branch("feature").is_err());
+        assert!(db.create_branch("main").is_err());
+        assert!(db.checkout("outra").is_err());
+
+        // As escritas na branch não aparecem na main, nem as da main na branch
+        db.checkout("feature").unwrap();
+        for i in 0..10 {
+            db.insert(format!("{i:03}"), "f".repeat(600)).unwrap();
+        }
+        db.delete("050".to_string()).unwrap();
+        db.checkout("main").unwrap();
+        assert_eq!(db.search("000").unwrap(), Some(Vec::new()));
+        assert_eq!(db.len().unwrap(), 120);
+        db.insert("200".to_string(), "m".repeat(600)).unwrap();
+        drop(db);
+
+        // A branch atual e as raízes das outras sobrevivem à reabertura; as escritas na main
+        // não reaproveitam as páginas da feature
+        let mut db = Db::open_with(name, options.clone()).unwrap();
+        assert_eq!(db.current_branch(), "main");
+        for round in 0..5 {
+            for i in (0..120).step_by(3) {
+                db.insert(format!("{i:03}"), format!("{round}").repeat(500)).unwrap();
+            }
+        }
+        db.checkout("feature").unwrap();
+        assert_eq!(db.len().unwrap(), 119);
+        assert_eq!(db.search("005").unwrap(), Some(b"f".repeat(600)));
+        assert_eq!(db.search("050").unwrap(), None);
+        assert_eq!(db.search("200").unwrap(), None);
+        assert_eq!(db.search("119").unwrap(), Some(b"v".repeat(119 * 17)));
+
+        // Chaves alteradas dos dois lados: o merge não aplica nada
+        let merge = db.merge("main").unwrap();
+        assert_eq!(merge.applied, 0);
+        assert_eq!(merge.conflicts, [b"000", b"003", b"006", b"009"]);
+        assert_eq!(db.search("200").unwrap(), None);
+
+        // Sem conflitos as alterações da feature chegam à main, e um novo merge não traz nada
+        db.checkout("main").unwrap();
+        for i in (0..10).step_by(3) {
+            db.insert(format!("{i:03}"), "f".repeat(600)).unwrap();
+        }
+        // Um merge que não chega ao arquivo não deixa nada aplicado, nem a base da feature alterada
+        let branches = db.branches().to_vec();
+        db.pager.set_file(fs::File::open(&filename).unwrap());
+        assert!(db.merge("feature").is_err());
+        db.pager.set_file(fs::File::options().read(true).write(true).open(&filename).unwrap());
+        assert_eq!(db.search("001").unwrap(), Some(b"v".repeat(17)));
+        assert_eq!(db.branches(), branches);
+        assert!(!db.in_transaction());
+
+        // As alterações e a nova base da feature são publicadas de uma só vez
+        let commit_seq = db.pager.commit_seq();
+        let merge = db.merge("feature").unwrap();
+        assert_eq!(merge, Merge { applied: 7, conflicts: Vec::new() });
+        assert_eq!(db.pager.commit_seq(), commit_seq + 1);
+        // As páginas que só a base antiga da feature alcançava voltaram para a lista livre: sobram só
+        // a página da tabela de raízes e a da lista livre
+        assert_eq!(unaccounted_pages(&mut db), 2);
+        assert_eq!(db.search("001").unwrap(), Some(b"f".repeat(600)));
+        assert_eq!(db.search("050").unwrap(), None);
+        assert_eq!(db.search("200").unwrap(), Some(b"m".repeat(600)));
+        assert_eq!(db.merge("feature").unwrap(), Merge::default());
+        assert!(db.merge("main").is_err());
+        drop(db);
+
+        // A compactação mantém as branches
+        compact_database(name).unwrap();
+        let mut db = Db::open_with(name, options).unwrap();
+        assert_eq!(db.branches().len(), 2);
+        db.checkout("feature").unwrap();
+        assert_eq!(db.len().unwrap(), 119);
+        assert_eq!(db.search("005").unwrap(), Some(b"f".repeat(600)));
+        db.checkout("main").unwrap();
+        assert_eq!(db.search("200").unwrap(), Some(b"m".repeat(600)));
+        drop(db);
+
+        fs::remove_file(&filename).unwrap();
+        let _ = fs::remove_file(format!("./databases/{name}.wal"));
+    }
+
+    #[test]
+    fn test_merge_sibling_and_nested_branches() {
+        let name = "test_merge_siblings";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+        let options = DbOptions { durability: Durability::None, ..Default::default() };
+
+        let mut db = Db::open_with(name, options).unwrap();
+        for i in 0..20 {
+            db.insert(format!("{i:03}"), "v").unwrap();
+        }
+        // "a" e "b" saem da main em momentos diferentes: só "b" tem a "p1"
+        db.create_branch("a").unwrap();
+        db.insert("p1", "main").unwrap();
+        db.create_branch("b").unwrap();
+
+        db.checkout("a").unwrap();
+        db.insert("x", "a").unwrap();
+        db.insert("001", "a").unwrap();
+        db.checkout("b").unwrap();
+        db.insert("y", "b").unwrap();
+        db.insert("002", "b").unwrap();
+        db.create_branch("c").unwrap();
+        db.checkout("c").unwrap();
+        db.insert("z", "c").unwrap();
+
+        // Entre irmãs, a base é a mais antiga das duas saídas da main, então a "p1" também chega
+        db.checkout("a").unwrap();
+        assert_eq!(db.merge("b").unwrap(), Merge { applied: 3, conflicts: Vec::new() });
+        assert_eq!(db.search("p1").unwrap().as_deref(), Some(&b"main"[..]));
+        assert_eq!(db.search("y").unwrap().as_deref(), Some(&b"b"[..]));
+        assert_eq!(db.search("001").unwrap().as_deref(), Some(&b"a"[..]));
+        assert_eq!(db.search("002").unwrap().as_deref(), Some(&b"b"[..]));
+        assert_eq!(db.merge("b").unwrap(), Merge::default());
+
+        // A neta traz para a main também o que a mãe dela fez antes de criá-la
+        db.checkout("main").unwrap();
+        assert_eq!(db.merge("c").unwrap(), Merge { applied: 3, conflicts: Vec::new() });
+        assert_eq!(db.search("y").unwrap().as_deref(), Some(&b"b"[..]));
+        assert_eq!(db.search("z").unwrap().as_deref(), Some(&b"c"[..]));
+        assert_eq!(db.search("x").unwrap(), None);
+        assert_eq!(db.merge("c").unwrap(), Merge::default());
+
+        // Alterações diferentes na mesma chave em irmãs são conflitos
+        db.checkout("a").unwrap();
+        db.insert("003", "a").unwrap();
+        db.checkout("b").unwrap();
+        db.insert("003", "b").unwrap();
+        assert_eq!(db.merge("a").unwrap().conflicts, [b"003"]);
+        drop(db);
+
+        fs::remove_file(&filename).unwrap();
+        let _ = fs::remove_file(format!("./databases/{name}.wal"));
+    }
+
+    #[test]
+    fn test_binary_keys_and_values() {
+        let name = "test_binary";
+        let filename = format!("./databases/{name}.kvdb");
+        let _ = fs::remove_file(&filename);
+        create_dir_all("./databases").unwrap();
+        create_database(name, Layout::default()).unwrap();
+
+        // Bytes que não são UTF-8 válido, inclusive zeros, nas chaves e nos valores
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = (0..=255u8)
+            .map(|b| (vec![0xff, b, 0x00], (0..(b as usize * 37)).map(|i| (i * 7 + b as usize) as u8).collect()))
+            .collect();
+        let mut db = Db::open(name).unwrap();
+        for (k, v) in &entries {
+            db.insert(k.clone(), v.clone()).unwrap();
+        }
+        db.insert("texto", "valor").unwrap();
+        drop(db);
+
+        // Registro do log com bytes inválidos em UTF-8, refeito ao reabrir
+        let mut wal = Wal::open(name, 257).unwrap();
+        wal.append(&WalRecord::Insert { key: vec![0xc3, 0x28], value: vec![0xfe; 10] }).unwrap();
+        drop(wal);
+
+        let mut db = Db::open(name).unwrap();
+        assert_eq!(db.len().unwrap(), 258);
+        assert_eq!(db.search([0xc3, 0x28]).unwrap(), Some(vec![0xfe; 10]));
+        assert_eq!(db.search("texto").unwrap().as_deref(), Some(&b"valor"[..]));
+        let scanned = db.snapshot().unwrap().scan_prefix([0xff]).unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(scanned, entries);
+        assert_eq!(db.rank([0xff, 0x80]).unwrap(), 128 + 2);
+        assert_eq!(db.select(2).unwrap(), Some(vec![0xff, 0x00, 0x00]));
+        db.delete(vec![0xff, 0x10, 0x00]).unwrap();
+        assert_eq!(db.search([0xff, 0x10, 0x00]).unwrap(), None);
+        drop(db);
+
+        // A compactação copia os valores binários como estão
+        compact_database(name).unwrap();
+        let mut db = Db::open(name).unwrap();
+        assert_eq!(db.search([0xff, 0xfe, 0x00]).unwrap().as_ref(), Some(&entries[0xfe].1));
+        drop(db);
+
+        fs::remove_file(&filename).unwrap();
+        let _ = fs::remove_file(format!("./databases/{name}.wal"));
+    }
 }
@@ -0,0 +1,124 @@
+use std::io::{Error, ErrorKind, Result};
+
+// Assinatura gravada no início de todo arquivo de banco de dados kvdb
+const MAGIC: [u8; 8] = *b"KVDBFILE";
+
+// Versão do formato em disco; deve ser incrementada a cada mudança incompatível
+pub const FORMAT_VERSION: u32 = 9;
+
+// Tamanho do cabeçalho serializado, incluindo o CRC32 final
+const ENCODED_SIZE: usize = 92;
+
+// A primeira página do arquivo guarda duas cópias do cabeçalho, em setores distintos.
+// Cada publicação grava a cópia mais antiga; se a escrita for interrompida, a outra
+// cópia continua válida e o banco volta à publicação anterior.
+pub const SLOT_SIZE: usize = 512;
+pub const SLOT_COUNT: usize = 2;
+
+// Cabeçalho do arquivo: identifica o formato e guarda a raiz publicada da B-Tree
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub version: u32,
+    pub page_size: u32,
+    pub order: u32,
+    pub key_count: u64,
+    pub root_offset: u64,
+    pub free_list_offset: u64,
+    pub free_list_length: u64,
+    // Sequência da última operação do WAL cujo resultado está publicado nesta raiz
+    pub wal_seq: u64,
+    // Número da publicação; a cópia com o maior número válido é a atual
+    pub commit_seq: u64,
+    // Organização da árvore (ver `btree::Engine`)
+    pub engine: u32,
+    // Tabela de raízes nomeadas (checkpoints e branches), gravada em uma cadeia de overflow
+    // como a lista livre
+    pub refs_offset: u64,
+    pub refs_length: u64,
+}
+
+impl Header {
+    pub fn new(page_size: u32, order: u32, engine: u32) -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            page_size,
+            order,
+            key_count: 0,
+            root_offset: 0,
+            free_list_offset: 0,
+            free_list_length: 0,
+            wal_seq: 0,
+            commit_seq: 0,
+            engine,
+            refs_offset: 0,
+            refs_length: 0,
+        }
+    }
+
+    // Offset da cópia do cabeçalho que a publicação atual deve sobrescrever
+    pub fn slot_offset(&self) -> u64 {
+        (self.commit_seq as usize % SLOT_COUNT * SLOT_SIZE) as u64
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(ENCODED_SIZE);
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&self.version.to_be_bytes());
+        data.extend_from_slice(&self.page_size.to_be_bytes());
+        data.extend_from_slice(&self.order.to_be_bytes());
+        data.extend_from_slice(&self.key_count.to_be_bytes());
+        data.extend_from_slice(&self.root_offset.to_be_bytes());
+        data.extend_from_slice(&self.free_list_offset.to_be_bytes());
+        data.extend_from_slice(&self.free_list_length.to_be_bytes());
+        data.extend_from_slice(&self.wal_seq.to_be_bytes());
+        data.extend_from_slice(&self.commit_seq.to_be_bytes());
+        data.extend_from_slice(&self.engine.to_be_bytes());
+        data.extend_from_slice(&self.refs_offset.to_be_bytes());
+        data.extend_from_slice(&self.refs_length.to_be_bytes());
+        let checksum = crc32fast::hash(&data);
+        data.extend_from_slice(&checksum.to_be_bytes());
+        data
+    }
+
+    // Lê e valida o cabeçalho; arquivos de outro formato ou versão são recusados
+    // e uma cópia com checksum inválido (escrita interrompida) é rejeitada
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() < ENCODED_SIZE || data[0..8] != MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "O arquivo não é um banco de dados kvdb (assinatura inválida)",
+            ));
+        }
+
+        let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_be_bytes(data[i..i + 8].try_into().unwrap());
+
+        let version = u32_at(8);
+        if version != FORMAT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Versão de formato {version} não suportada (esperada {FORMAT_VERSION})"),
+            ));
+        }
+
+        let checksum = u32_at(ENCODED_SIZE - 4);
+        if checksum != crc32fast::hash(&data[..ENCODED_SIZE - 4]) {
+            return Err(Error::new(ErrorKind::InvalidData, "Cabeçalho com checksum inválido"));
+        }
+
+        Ok(Header {
+            version,
+            page_size: u32_at(12),
+            order: u32_at(16),
+            key_count: u64_at(20),
+            root_offset: u64_at(28),
+            free_list_offset: u64_at(36),
+            free_list_length: u64_at(44),
+            wal_seq: u64_at(52),
+            commit_seq: u64_at(60),
+            engine: u32_at(68),
+            refs_offset: u64_at(72),
+            refs_length: u64_at(80),
+        })
+    }
+}
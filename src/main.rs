@@ -7,10 +7,17 @@ use ratatui::prelude::{CrosstermBackend};
 use std::io::*;
 
 mod app;
+mod batch;
+mod cache;
 mod cli;
+mod cursor;
 mod db;
+mod header;
+mod node_page;
 mod pager;
+mod snapshot;
 mod btree;
+mod wal;
 
 use app::App;
 use cli::{run};
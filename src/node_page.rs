@@ -0,0 +1,477 @@
+use std::cmp::Ordering;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::btree::{Node, Value};
+use crate::pager::{CorruptPage, Pager};
+
+// Layout de uma página de nó (slotted page), todos os inteiros em big-endian:
+//
+//   [tipo u8][flags u8][quantidade de células u16][início da área de células u16][reservado u16]
+//   [filho mais à direita u64 | folhas: próxima folha u64][entradas do filho mais à direita u64]
+//                                                                 <- cabeçalho (HEADER_SIZE bytes)
+//   [offset da célula 0 u16][offset da célula 1 u16]...           <- diretório, em ordem de chave
+//   ... espaço livre ...
+//   [célula n-1]...[célula 1][célula 0]                           <- área de células, no fim da página
+//
+// Célula: [filho à esquerda u64][entradas do filho u64]        só em nós internos
+//         [tamanho da chave u16][chave]
+//         [LONG_KEY | tamanho do prefixo u16][prefixo][offset u64][tamanho u64]   chave longa
+//         [0 u8][tamanho do valor u16][valor]          valor guardado no nó
+//         [1 u8][offset u64][tamanho u64]              valor em uma cadeia de overflow
+//
+// Nos nós internos da B+Tree (tipo SEPARATORS) a célula termina na chave: não há valor.
+// Uma chave maior que MAX_INLINE_KEY_SIZE fica inteira em uma cadeia de overflow e a célula guarda
+// só os seus primeiros KEY_PREFIX_SIZE bytes, que bastam para quase todas as comparações da busca.
+// As folhas da B+Tree guardam o offset da folha seguinte (0 na última); a flag LINKED na raiz
+// indica que todas as ligações da árvore estão corretas (ver `Node::linked`).
+//
+// O diretório ordenado permite a busca binária direto nos bytes da página, sem decodificar o nó,
+// e a quantidade de entradas de cada subárvore permite contar e posicionar por índice.
+const HEADER_SIZE: usize = 24;
+const CELL_POINTER_SIZE: usize = 2;
+// Offset do filho + quantidade de entradas da subárvore
+const CHILD_SIZE: usize = 16;
+
+const LEAF: u8 = 1;
+const INTERNAL: u8 = 2;
+const SEPARATORS: u8 = 3;
+
+const LINKED: u8 = 1;
+
+const INLINE: u8 = 0;
+const OVERFLOW: u8 = 1;
+
+// Chaves maiores que isso vão para páginas de overflow; na célula elas ocupam o mesmo espaço
+// que a maior chave guardada inteira (prefixo + offset e tamanho da cadeia)
+pub const MAX_INLINE_KEY_SIZE: usize = 256;
+const KEY_PREFIX_SIZE: usize = MAX_INLINE_KEY_SIZE - 16;
+// Bit alto do tamanho da chave: a célula guarda só o prefixo de uma chave longa
+const LONG_KEY: u16 = 0x8000;
+
+pub fn is_long_key(key: &[u8]) -> bool {
+    key.len() > MAX_INLINE_KEY_SIZE
+}
+
+// Bytes que uma entrada ocupa na página: ponteiro no diretório + célula
+pub fn entry_size(key: &[u8], value: Option<&Value>, is_leaf: bool) -> usize {
+    let child = if is_leaf { 0 } else { CHILD_SIZE };
+    let key = if is_long_key(key) { KEY_PREFIX_SIZE + 16 } else { key.len() };
+    let value = match value {
+        Some(Value::Inline(value)) => 1 + 2 + value.len(),
+        Some(Value::Overflow { .. }) => 1 + 16,
+        None => 0,
+    };
+    CELL_POINTER_SIZE + child + 2 + key + value
+}
+
+// Maior entrada possível para as chaves e valores guardados no nó, com os limites indicados
+pub const fn max_entry_size(max_key_size: usize, max_inline_value_size: usize) -> usize {
+    CELL_POINTER_SIZE + CHILD_SIZE + 2 + max_key_size + 1 + 2 + max_inline_value_size
+}
+
+pub fn encoded_size(node: &Node) -> usize {
+    HEADER_SIZE
+        + node
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| entry_size(key, node.values.get(i), node.is_leaf))
+            .sum::<usize>()
+}
+
+// Monta a página de um nó com `page_data_size` bytes. As chaves longas já devem estar gravadas
+// nas cadeias de `node.key_chains`
+pub fn encode(node: &Node, page_data_size: usize) -> Result<Vec<u8>> {
+    if encoded_size(node) > page_data_size {
+        return Err(Error::new(ErrorKind::InvalidData, "Nó excede o tamanho de uma página"));
+    }
+
+    let mut page = vec![0; page_data_size];
+    let mut cell_start = page_data_size;
+    let mut key_chains = node.key_chains.iter();
+    for (i, key) in node.keys.iter().enumerate() {
+        let mut cell = Vec::new();
+        if !node.is_leaf {
+            cell.extend_from_slice(&node.children[i].to_be_bytes());
+            cell.extend_from_slice(&node.counts[i].to_be_bytes());
+        }
+        if is_long_key(key) {
+            let chain = key_chains
+                .next()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Chave longa sem cadeia de overflow"))?;
+            cell.extend_from_slice(&(LONG_KEY | KEY_PREFIX_SIZE as u16).to_be_bytes());
+            cell.extend_from_slice(&key[..KEY_PREFIX_SIZE]);
+            cell.extend_from_slice(&chain.to_be_bytes());
+            cell.extend_from_slice(&(key.len() as u64).to_be_bytes());
+        } else {
+            cell.extend_from_slice(&(key.len() as u16).to_be_bytes());
+            cell.extend_from_slice(key);
+        }
+        match node.values.get(i) {
+            Some(Value::Inline(value)) => {
+                cell.push(INLINE);
+                cell.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                cell.extend_from_slice(value);
+            }
+            Some(Value::Overflow { offset, length }) => {
+                cell.push(OVERFLOW);
+                cell.extend_from_slice(&offset.to_be_bytes());
+                cell.extend_from_slice(&length.to_be_bytes());
+            }
+            None => {}
+        }
+
+        cell_start -= cell.len();
+        page[cell_start..cell_start + cell.len()].copy_from_slice(&cell);
+        let pointer = HEADER_SIZE + i * CELL_POINTER_SIZE;
+        page[pointer..pointer + 2].copy_from_slice(&(cell_start as u16).to_be_bytes());
+    }
+
+    // Nó interno sem valores: nó de separadores da B+Tree
+    page[0] = match (node.is_leaf, node.values.is_empty()) {
+        (true, _) => LEAF,
+        (false, true) => SEPARATORS,
+        (false, false) => INTERNAL,
+    };
+    if node.linked {
+        page[1] = LINKED;
+    }
+    page[2..4].copy_from_slice(&(node.keys.len() as u16).to_be_bytes());
+    page[4..6].copy_from_slice(&(cell_start as u16).to_be_bytes());
+    if node.is_leaf {
+        page[8..16].copy_from_slice(&node.next.unwrap_or(0).to_be_bytes());
+    } else {
+        page[8..16].copy_from_slice(&node.children[node.keys.len()].to_be_bytes());
+        page[16..24].copy_from_slice(&node.counts[node.keys.len()].to_be_bytes());
+    }
+    Ok(page)
+}
+
+// Bytes de uma chave na célula e a cadeia de overflow (offset, tamanho) de uma chave longa
+type StoredKey<'a> = (&'a [u8], Option<(u64, u64)>);
+
+// Visão somente leitura sobre os bytes de uma página de nó. Toda leitura confere os limites
+// da página; um conteúdo inconsistente é reportado como página corrompida
+pub struct NodePage<'a> {
+    data: &'a [u8],
+    offset: u64,
+}
+
+impl<'a> NodePage<'a> {
+    // `offset` é a posição da página no arquivo, usada apenas nos erros de corrupção
+    pub fn new(data: &'a [u8], offset: u64) -> Result<Self> {
+        let page = NodePage { data, offset };
+        if data.len() < HEADER_SIZE || !matches!(data[0], LEAF | INTERNAL | SEPARATORS) {
+            return Err(page.corrupt());
+        }
+        if HEADER_SIZE + page.key_count() * CELL_POINTER_SIZE > data.len() {
+            return Err(page.corrupt());
+        }
+        Ok(page)
+    }
+
+    fn corrupt(&self) -> Error {
+        CorruptPage::error(self.offset)
+    }
+
+    fn bytes(&self, start: usize, length: usize) -> Result<&'a [u8]> {
+        self.data.get(start..start + length).ok_or_else(|| self.corrupt())
+    }
+
+    fn u16_at(&self, start: usize) -> Result<usize> {
+        Ok(u16::from_be_bytes(self.bytes(start, 2)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64_at(&self, start: usize) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.bytes(start, 8)?.try_into().unwrap()))
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.data[0] == LEAF
+    }
+
+    // Nós de separadores da B+Tree não guardam valores
+    pub fn has_values(&self) -> bool {
+        self.data[0] != SEPARATORS
+    }
+
+    // Raiz de uma árvore em que todas as folhas apontam para a seguinte
+    pub fn linked(&self) -> bool {
+        self.data[1] & LINKED != 0
+    }
+
+    // Offset da folha seguinte (None na última folha e nos nós internos)
+    pub fn next(&self) -> Result<Option<u64>> {
+        if !self.is_leaf() {
+            return Ok(None);
+        }
+        Ok(Some(self.u64_at(8)?).filter(|&next| next != 0))
+    }
+
+    // Quantidade de chaves do nó
+    pub fn key_count(&self) -> usize {
+        u16::from_be_bytes(self.data[2..4].try_into().unwrap()) as usize
+    }
+
+    // Início da célula `i` (depois do ponteiro para o filho, em nós internos)
+    fn cell(&self, i: usize) -> Result<usize> {
+        let start = self.u16_at(HEADER_SIZE + i * CELL_POINTER_SIZE)?;
+        Ok(if self.is_leaf() { start } else { start + CHILD_SIZE })
+    }
+
+    // Bytes da chave guardados na célula (o prefixo, se a chave for longa) e, para uma chave longa,
+    // o offset e o tamanho da cadeia com a chave inteira
+    fn stored_key(&self, i: usize) -> Result<StoredKey<'a>> {
+        let cell = self.cell(i)?;
+        let length = self.u16_at(cell)?;
+        if length & LONG_KEY as usize == 0 {
+            return Ok((self.bytes(cell + 2, length)?, None));
+        }
+        let length = length & !(LONG_KEY as usize);
+        let chain = (self.u64_at(cell + 2 + length)?, self.u64_at(cell + 10 + length)?);
+        Ok((self.bytes(cell + 2, length)?, Some(chain)))
+    }
+
+    // Chave `i`: inteira, ou a referência à cadeia de overflow de uma chave longa
+    // (remontada com `Value::read`)
+    pub fn key(&self, i: usize) -> Result<Value> {
+        Ok(match self.stored_key(i)? {
+            (key, None) => Value::Inline(key.to_vec()),
+            (_, Some((offset, length))) => Value::Overflow { offset, length },
+        })
+    }
+
+    // Compara a chave `i` com `key` usando só a página. Quando a chave `i` é longa e o seu prefixo
+    // é igual ao início de `key`, só a chave inteira decide: devolve a cadeia (offset e tamanho)
+    fn compare(&self, i: usize, key: &[u8]) -> Result<std::result::Result<Ordering, (u64, u64)>> {
+        let (stored, chain) = self.stored_key(i)?;
+        let Some(chain) = chain else {
+            return Ok(Ok(stored.cmp(key)));
+        };
+        let n = key.len().min(stored.len());
+        Ok(match stored.cmp(&key[..n]) {
+            Ordering::Equal if key.len() > n => Err(chain),
+            // `key` é um prefixo da chave longa
+            Ordering::Equal => Ok(Ordering::Greater),
+            ordering => Ok(ordering),
+        })
+    }
+
+    pub fn value(&self, i: usize) -> Result<Value> {
+        if !self.has_values() {
+            return Err(self.corrupt());
+        }
+        let cell = self.cell(i)?;
+        let start = match self.u16_at(cell)? {
+            length if length & LONG_KEY as usize != 0 => cell + 2 + (length & !(LONG_KEY as usize)) + 16,
+            length => cell + 2 + length,
+        };
+        match self.bytes(start, 1)?[0] {
+            INLINE => {
+                let length = self.u16_at(start + 1)?;
+                Ok(Value::Inline(self.bytes(start + 3, length)?.to_vec()))
+            }
+            OVERFLOW => Ok(Value::Overflow {
+                offset: self.u64_at(start + 1)?,
+                length: self.u64_at(start + 9)?,
+            }),
+            _ => Err(self.corrupt()),
+        }
+    }
+
+    // Filho `i` de um nó interno: à esquerda da chave `i`, ou o mais à direita se `i == key_count()`
+    pub fn child(&self, i: usize) -> Result<u64> {
+        if i == self.key_count() {
+            return self.u64_at(8);
+        }
+        self.u64_at(self.u16_at(HEADER_SIZE + i * CELL_POINTER_SIZE)?)
+    }
+
+    // Quantidade de entradas na subárvore do filho `i`
+    pub fn child_count(&self, i: usize) -> Result<u64> {
+        if i == self.key_count() {
+            return self.u64_at(16);
+        }
+        self.u64_at(self.u16_at(HEADER_SIZE + i * CELL_POINTER_SIZE)? + 8)
+    }
+
+    // Quantidade de entradas na subárvore deste nó
+    pub fn count(&self) -> Result<u64> {
+        let mut count = if self.has_values() { self.key_count() as u64 } else { 0 };
+        if !self.is_leaf() {
+            for i in 0..=self.key_count() {
+                count += self.child_count(i)?;
+            }
+        }
+        Ok(count)
+    }
+
+    // Busca binária pela chave direto nos bytes da página: Ok(i) se encontrada,
+    // Err(i) com a posição onde ela ficaria. Só serve para chaves de até KEY_PREFIX_SIZE bytes,
+    // que nunca empatam com o prefixo de uma chave longa (ver `search`)
+    fn search(&self, key: &[u8]) -> Result<std::result::Result<usize, usize>> {
+        let (mut low, mut high) = (0, self.key_count());
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.compare(mid, key)?.unwrap_or(Ordering::Less) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(Ok(mid)),
+            }
+        }
+        Ok(Err(low))
+    }
+
+    // Chaves longas do nó: índice, offset e tamanho da cadeia de overflow
+    pub fn long_keys(&self) -> Result<Vec<(usize, u64, u64)>> {
+        let mut long_keys = Vec::new();
+        for i in 0..self.key_count() {
+            if let (_, Some((offset, length))) = self.stored_key(i)? {
+                long_keys.push((i, offset, length));
+            }
+        }
+        Ok(long_keys)
+    }
+
+    // Decodifica o nó completo (usado pelas operações que alteram a árvore). As chaves longas
+    // ficam vazias: quem decodifica as remonta a partir de `long_keys`
+    pub fn to_node(&self) -> Result<Node> {
+        let mut node = Node::new(self.is_leaf());
+        for i in 0..self.key_count() {
+            node.keys.push(match self.stored_key(i)? {
+                (key, None) => key.to_vec(),
+                (_, Some(_)) => Vec::new(),
+            });
+            if self.has_values() {
+                node.values.push(self.value(i)?);
+            }
+            if !node.is_leaf {
+                node.children.push(self.child(i)?);
+                node.counts.push(self.child_count(i)?);
+            }
+        }
+        if !node.is_leaf {
+            node.children.push(self.child(self.key_count())?);
+            node.counts.push(self.child_count(self.key_count())?);
+        }
+        node.next = self.next()?;
+        node.linked = self.linked();
+        Ok(node)
+    }
+}
+
+// Abre a página do nó e busca a chave nela. Uma chave maior que KEY_PREFIX_SIZE pode empatar com o
+// prefixo de uma chave longa guardada: nesse caso a chave guardada é remontada da cadeia de overflow,
+// e a página é relida a cada passo para que o pager fique livre para ler a cadeia
+pub fn search<'a>(pager: &'a mut Pager, offset: u64, key: &[u8]) -> Result<(NodePage<'a>, std::result::Result<usize, usize>)> {
+    if key.len() <= KEY_PREFIX_SIZE {
+        let page = NodePage::new(pager.page(offset)?, offset)?;
+        let found = page.search(key)?;
+        return Ok((page, found));
+    }
+
+    let (mut low, mut high) = (0, NodePage::new(pager.page(offset)?, offset)?.key_count());
+    let mut found = None;
+    while low < high {
+        let mid = (low + high) / 2;
+        let ordering = match NodePage::new(pager.page(offset)?, offset)?.compare(mid, key)? {
+            Ok(ordering) => ordering,
+            Err((chain, length)) => pager.read_overflow(chain, length as usize)?.as_slice().cmp(key),
+        };
+        match ordering {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => {
+                found = Some(mid);
+                break;
+            }
+        }
+    }
+    Ok((NodePage::new(pager.page(offset)?, offset)?, found.ok_or(low)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_search_in_place() {
+        let mut node = Node::new(false);
+        for (i, key) in ["banana", "laranja", "uva"].iter().enumerate() {
+            node.keys.push(key.as_bytes().to_vec());
+            node.values.push(if i == 1 {
+                Value::Overflow { offset: 8192, length: 5000 }
+            } else {
+                Value::Inline(key.repeat(2).into_bytes())
+            });
+            node.children.push(4096 * (i as u64 + 10));
+            node.counts.push(i as u64 + 1);
+        }
+        node.children.push(4096 * 20);
+        node.counts.push(100);
+
+        let data = encode(&node, 4092).unwrap();
+        let page = NodePage::new(&data, 4096).unwrap();
+        assert_eq!(page.key_count(), 3);
+        assert_eq!(page.search(b"laranja").unwrap(), Ok(1));
+        assert_eq!(page.search(b"abacaxi").unwrap(), Err(0));
+        assert_eq!(page.search(b"zimbro").unwrap(), Err(3));
+        assert_eq!(page.child(3).unwrap(), 4096 * 20);
+        assert_eq!(page.child_count(1).unwrap(), 2);
+        assert_eq!(page.count().unwrap(), 3 + 1 + 2 + 3 + 100);
+        assert!(matches!(page.value(1).unwrap(), Value::Overflow { offset: 8192, length: 5000 }));
+
+        let decoded = page.to_node().unwrap();
+        assert_eq!(decoded.keys, node.keys);
+        assert_eq!(decoded.children, node.children);
+        assert_eq!(decoded.counts, node.counts);
+
+        // Um ponteiro do diretório fora da página é corrupção, não pânico
+        let mut data = data;
+        data[HEADER_SIZE..HEADER_SIZE + 2].copy_from_slice(&u16::MAX.to_be_bytes());
+        let page = NodePage::new(&data, 4096).unwrap();
+        assert!(page.key(0).is_err());
+        assert!(page.to_node().is_err());
+
+        // Nó de separadores da B+Tree
+        node.values.clear();
+        let data = encode(&node, 4092).unwrap();
+        let page = NodePage::new(&data, 4096).unwrap();
+        assert!(!page.has_values());
+        assert_eq!(page.count().unwrap(), 1 + 2 + 3 + 100);
+        assert_eq!(page.search(b"uva").unwrap(), Ok(2));
+        assert_eq!(page.to_node().unwrap().children, node.children);
+
+        // Folha da B+Tree ligada à seguinte, como raiz de uma árvore ligada
+        let mut leaf = Node::new(true);
+        leaf.keys.push(b"banana".to_vec());
+        leaf.values.push(Value::Inline(b"amarela".to_vec()));
+        leaf.next = Some(4096 * 7);
+        leaf.linked = true;
+        let data = encode(&leaf, 4092).unwrap();
+        let page = NodePage::new(&data, 4096).unwrap();
+        assert_eq!(page.next().unwrap(), Some(4096 * 7));
+        assert!(page.linked());
+        let decoded = page.to_node().unwrap();
+        assert_eq!((decoded.next, decoded.linked), (leaf.next, true));
+
+        // Chave longa: a célula guarda o prefixo e a cadeia, e o valor continua logo depois
+        let long_key = b"m".repeat(MAX_INLINE_KEY_SIZE + 100);
+        leaf.keys.push(long_key.clone());
+        leaf.values.push(Value::Inline(b"longa".to_vec()));
+        leaf.keys.push(b"n".to_vec());
+        leaf.values.push(Value::Inline(b"curta".to_vec()));
+        assert!(encode(&leaf, 4092).is_err(), "Chave longa sem cadeia");
+        leaf.key_chains.push(4096 * 9);
+        let data = encode(&leaf, 4092).unwrap();
+        let page = NodePage::new(&data, 4096).unwrap();
+        assert!(matches!(page.key(1).unwrap(), Value::Overflow { offset: 36864, length } if length == long_key.len() as u64));
+        assert!(matches!(page.value(1).unwrap(), Value::Inline(value) if value == b"longa"));
+        assert_eq!(page.long_keys().unwrap(), vec![(1, 4096 * 9, long_key.len() as u64)]);
+        assert_eq!(page.search(b"m").unwrap(), Err(1));
+        assert_eq!(page.search(&b"m".repeat(KEY_PREFIX_SIZE)).unwrap(), Err(1));
+        assert_eq!(page.search(b"mn").unwrap(), Err(2));
+        assert_eq!(page.search(b"n").unwrap(), Ok(2));
+        assert_eq!(page.compare(1, &long_key).unwrap(), Err((4096 * 9, long_key.len() as u64)));
+    }
+}
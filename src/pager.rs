@@ -1,33 +1,511 @@
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::fs::{File};
-use std::io::{Read, Write, Seek, SeekFrom, Result};
+use std::rc::Rc;
+use std::io::{Read, Write, Seek, SeekFrom, Result, Error, ErrorKind};
 
+use crate::btree::{self, BTree, DEFAULT_ORDER, Engine, Node};
+use crate::cache::{CacheStats, LruCache};
 use crate::db::open_database;
+use crate::header::{Header, SLOT_COUNT, SLOT_SIZE};
+
+// Tamanho de cada página do arquivo (cabeçalho, nós e páginas de overflow), escolhido na
+// criação do banco e gravado no cabeçalho: uma potência de dois entre 4 KiB e 64 KiB
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+pub const MIN_PAGE_SIZE: usize = 4096;
+pub const MAX_PAGE_SIZE: usize = 65536;
+
+// Toda página (exceto o cabeçalho do arquivo) começa com o CRC32 do restante da página
+const CHECKSUM_SIZE: usize = 4;
+
+// Cabeçalho de uma página de overflow: offset da próxima página (u64) + bytes úteis na página (u32)
+const OVERFLOW_HEADER_SIZE: usize = 12;
+
+// Quantidade padrão de páginas mantidas em memória (1 MiB)
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+// Erro de corrupção: a página no offset indicado não confere com o checksum gravado
+#[derive(Debug)]
+pub struct CorruptPage {
+    pub offset: u64,
+}
+
+impl fmt::Display for CorruptPage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Página corrompida no offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for CorruptPage {}
+
+impl CorruptPage {
+    pub fn error(offset: u64) -> Error {
+        Error::new(ErrorKind::InvalidData, CorruptPage { offset })
+    }
+}
+
+// Quando os dados gravados são forçados para o disco (fsync)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    // Nunca sincroniza; o sistema operacional decide quando gravar
+    None,
+    // Sincroniza as páginas antes e o cabeçalho depois de cada publicação da raiz
+    #[default]
+    Commit,
+    // Sincroniza cada escrita de página, além das publicações
+    Page,
+}
+
+impl std::str::FromStr for Durability {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Durability::None),
+            "commit" => Ok(Durability::Commit),
+            "page" => Ok(Durability::Page),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Modo de durabilidade desconhecido: {s} (use none, commit ou page)"),
+            )),
+        }
+    }
+}
+
+// Como as páginas são lidas do arquivo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    // seek + read a cada leitura
+    #[default]
+    File,
+    // Arquivo mapeado em memória; as leituras são cópias diretas do mapeamento
+    Mmap,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "file" => Ok(Backend::File),
+            "mmap" => Ok(Backend::Mmap),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Modo de leitura desconhecido: {s} (use file ou mmap)"),
+            )),
+        }
+    }
+}
+
+// Publicações (sequências de commit) fixadas por snapshots abertos e quantos snapshots fixam cada uma
+type Pins = Rc<RefCell<BTreeMap<u64, usize>>>;
+
+// Mantém fixada a publicação em que foi criado enquanto existir (ver `Pager::pin`)
+pub struct Pin {
+    pins: Pins,
+    seq: u64,
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        let mut pins = self.pins.borrow_mut();
+        if let Some(count) = pins.get_mut(&self.seq) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.seq);
+            }
+        }
+    }
+}
+
+// Posição das listas de liberações e alocações pendentes no início de uma operação (ver `Pager::pending_mark`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PendingMark {
+    freed: usize,
+    allocated: usize,
+}
+
+// Checkpoint nomeado: uma raiz publicada que continua legível (e restaurável) depois das
+// escritas seguintes. As páginas alcançáveis por ela nunca voltam para a lista livre
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub name: String,
+    pub root_offset: Option<u64>,
+    pub key_count: u64,
+    // Segundos desde a época Unix
+    pub timestamp: u64,
+}
+
+// Branch: uma raiz que recebe escritas independentes das outras branches (ver `Db::create_branch`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub name: String,
+    // Raiz e quantidade de chaves da branch; enquanto ela é a atual, valem as do cabeçalho
+    pub root_offset: Option<u64>,
+    pub key_count: u64,
+    // Branch de onde esta foi criada e a raiz dela na criação (ou no último merge): a base
+    // das comparações do merge
+    pub parent: Option<String>,
+    pub base_offset: Option<u64>,
+    // Ordem em que a base foi registrada entre todas as branches; entre duas bases tiradas da mesma
+    // branch, a de ordem menor é a mais antiga
+    pub base_seq: u64,
+}
+
+// Tabela de raízes nomeadas gravada no arquivo
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Refs {
+    checkpoints: Vec<Checkpoint>,
+    // Vazia até a primeira branch ser criada; a branch atual é a do cabeçalho
+    branches: Vec<Branch>,
+    current_branch: String,
+}
+
+impl Default for Refs {
+    fn default() -> Self {
+        Refs { checkpoints: Vec::new(), branches: Vec::new(), current_branch: MAIN_BRANCH.to_string() }
+    }
+}
+
+// Tabela de raízes em memória e onde ela está gravada, guardada por `Pager::save_refs` para desfazer
+// uma alteração cuja publicação falhou
+pub struct SavedRefs {
+    refs: Refs,
+    pages: Vec<u64>,
+    offset: u64,
+    length: u64,
+}
+
+// Branch que existe desde a criação do banco
+pub const MAIN_BRANCH: &str = "main";
+
+// Parâmetros escolhidos na criação do banco de dados e gravados no cabeçalho
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub page_size: usize,
+    pub order: usize,
+    pub engine: Engine,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            page_size: DEFAULT_PAGE_SIZE,
+            order: DEFAULT_ORDER,
+            engine: Engine::default(),
+        }
+    }
+}
 
 pub struct Pager {
     file: File,
+    header: Header,
+    page_size: usize,
+    engine: Engine,
+    durability: Durability,
+    // Mapeamento do arquivo quando o backend é `Backend::Mmap`. As escritas continuam
+    // passando pelo arquivo; o mapeamento é refeito quando uma leitura passa do seu fim.
+    mmap: Option<Mmap>,
+    // Conteúdo (já verificado) das páginas lidas ou gravadas recentemente
+    cache: LruCache<u64, Vec<u8>>,
+    // Nós já decodificados, para evitar desserializar a mesma página várias vezes. São
+    // compartilhados: só quem vai alterar um nó paga pela cópia (ver `Node::load_mut`)
+    nodes: LruCache<u64, Rc<Node>>,
+    // Última página lida por `page` quando o cache está desligado
+    scratch: Vec<u8>,
+    // Páginas livres que podem ser reaproveitadas imediatamente
+    free_pages: Vec<u64>,
+    // Páginas liberadas pela operação atual; ainda são alcançáveis pela raiz gravada
+    // no arquivo, então só entram na lista livre quando a nova raiz é publicada
+    pending_free: Vec<u64>,
+    // Páginas entregues por `allocate` desde a última publicação; se a operação que as gravou for
+    // desfeita, nenhuma raiz as referencia e elas voltam direto para a lista livre
+    allocated: Vec<u64>,
+    // Páginas que guardam a lista livre persistida atualmente no arquivo
+    free_list_pages: Vec<u64>,
+    // Páginas liberadas enquanto havia snapshots abertos, com a publicação que as liberou: continuam
+    // alcançáveis pelas raízes mais antigas e só voltam a ser usadas quando nenhum snapshot anterior
+    // à liberação existir. Vão para a lista persistida (ao reabrir o arquivo não há snapshots)
+    retained: Vec<(u64, u64)>,
+    pins: Pins,
+    refs: Refs,
+    // Páginas que guardam a tabela de raízes gravada atualmente no arquivo
+    refs_pages: Vec<u64>,
+    // Páginas alcançáveis pelas raízes preservadas (ver `preserved_roots`); é montado percorrendo
+    // as árvores na primeira publicação depois de abrir o arquivo ou de alterar a tabela
+    preserved: Option<HashSet<u64>>,
 }
 
 impl Pager {
-    // Abre ou cria o arquivo do banco de dados
-    pub fn new(db_name: &str) -> Self {
-        let file = open_database(db_name);
+    // Abre o arquivo do banco de dados, recusando arquivos de outro formato
+    pub fn new(db_name: &str) -> Result<Self> {
+        let file = open_database(db_name)?;
 
-        Pager { file }
+        Pager::from_file(file)
+    }
+
+    // Usa um arquivo já aberto (e já com cabeçalho) como banco de dados
+    pub fn from_file(mut file: File) -> Result<Self> {
+        let mut data = vec![0; SLOT_COUNT * SLOT_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        let read = file.read(&mut data)?;
+        let header = Self::newest_header(&data[..read])?;
+
+        let page_size = header.page_size as usize;
+        let engine = Engine::from_id(header.engine).and_then(|engine| {
+            Self::check_layout(&Layout { page_size, order: header.order as usize, engine })?;
+            Ok(engine)
+        });
+        let engine = engine.map_err(|e| {
+            Error::new(ErrorKind::InvalidData, format!("Banco de dados incompatível: {e}"))
+        })?;
+
+        let mut pager = Pager {
+            file,
+            header,
+            page_size,
+            engine,
+            durability: Durability::default(),
+            mmap: None,
+            cache: LruCache::new(DEFAULT_CACHE_CAPACITY),
+            nodes: LruCache::new(DEFAULT_CACHE_CAPACITY),
+            scratch: Vec::new(),
+            free_pages: Vec::new(),
+            pending_free: Vec::new(),
+            allocated: Vec::new(),
+            free_list_pages: Vec::new(),
+            retained: Vec::new(),
+            pins: Pins::default(),
+            refs: Refs::default(),
+            refs_pages: Vec::new(),
+            preserved: None,
+        };
+        pager.load_free_list()?;
+        pager.load_refs()?;
+        Ok(pager)
+    }
+
+    // Valida o tamanho de página e a ordem da B-Tree de um banco de dados
+    pub fn check_layout(layout: &Layout) -> Result<()> {
+        let page_size = layout.page_size;
+        if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Tamanho de página {page_size} inválido (potência de dois entre {MIN_PAGE_SIZE} e {MAX_PAGE_SIZE})"),
+            ));
+        }
+        btree::check_order(layout.order)
+    }
+
+    // Primeira página de um arquivo novo, com as duas cópias do cabeçalho já válidas
+    pub fn new_header(layout: &Layout) -> Vec<u8> {
+        let header = Header::new(layout.page_size as u32, layout.order as u32, layout.engine.id()).to_bytes();
+        let mut data = vec![0; layout.page_size];
+        for slot in 0..SLOT_COUNT {
+            data[slot * SLOT_SIZE..slot * SLOT_SIZE + header.len()].copy_from_slice(&header);
+        }
+        data
+    }
+
+    // Escolhe a cópia válida mais recente do cabeçalho
+    fn newest_header(data: &[u8]) -> Result<Header> {
+        let mut newest: Option<Header> = None;
+        let mut last_error = None;
+        for slot in data.chunks(SLOT_SIZE).take(SLOT_COUNT) {
+            match Header::from_bytes(slot) {
+                Ok(header) if newest.as_ref().is_none_or(|h| header.commit_seq > h.commit_seq) => {
+                    newest = Some(header);
+                }
+                Ok(_) => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match (newest, last_error) {
+            (Some(header), _) => Ok(header),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(Error::new(ErrorKind::InvalidData, "Arquivo sem cabeçalho")),
+        }
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    // Bytes disponíveis para o conteúdo de uma página
+    pub fn page_data_size(&self) -> usize {
+        self.page_size - CHECKSUM_SIZE
+    }
+
+    fn overflow_data_size(&self) -> usize {
+        self.page_data_size() - OVERFLOW_HEADER_SIZE
+    }
+
+    // Ordem (grau mínimo) da B-Tree gravada no cabeçalho
+    pub fn order(&self) -> usize {
+        self.header.order as usize
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn layout(&self) -> Layout {
+        Layout { page_size: self.page_size, order: self.order(), engine: self.engine }
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) -> Result<()> {
+        self.mmap = match backend {
+            Backend::File => None,
+            Backend::Mmap => Some(self.map_file()?),
+        };
+        Ok(())
+    }
+
+    fn map_file(&self) -> Result<Mmap> {
+        // Seguro enquanto nenhum outro processo truncar o arquivo: as páginas publicadas
+        // nunca são sobrescritas e o arquivo só cresce enquanto o paginador está aberto
+        unsafe { Mmap::map(&self.file) }
+    }
+
+    // Trecho do arquivo mapeado, refazendo o mapeamento se o arquivo cresceu
+    // (só é usado com o backend `Backend::Mmap`)
+    fn mapped(&mut self, offset: u64, length: usize) -> Result<&[u8]> {
+        let start = offset as usize;
+        let end = start + length;
+        if self.mmap.as_ref().is_none_or(|map| end > map.len()) {
+            self.mmap = Some(self.map_file()?);
+        }
+
+        let map = self.mmap.as_ref().unwrap();
+        if end > map.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Leitura além do fim do arquivo"));
+        }
+        Ok(&map[start..end])
+    }
+
+    // Capacidade dos caches de páginas e de nós, em páginas (zero desliga os caches)
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache.set_capacity(capacity);
+        self.nodes.set_capacity(capacity);
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn node_cache_stats(&self) -> CacheStats {
+        self.nodes.stats()
+    }
+
+    pub fn cached_node(&mut self, offset: u64) -> Option<Rc<Node>> {
+        self.nodes.get(&offset).cloned()
+    }
+
+    pub fn cache_node(&mut self, offset: u64, node: Rc<Node>) {
+        self.nodes.insert(offset, node);
     }
 
     // Escreve bytes em uma posição específica (offset)
     pub fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<()> {
         self.file.seek(SeekFrom::Start(offset))?;
         self.file.write_all(data)?;
+        if self.durability == Durability::Page {
+            self.file.sync_data()?;
+        }
         Ok(())
     }
 
-    // Lê bytes de uma posição específica
-    pub fn read_at(&mut self, offset: u64, length: usize) -> Result<Vec<u8>> {
+    // Força para o disco o que já foi escrito, a menos que a durabilidade esteja desligada
+    fn sync(&mut self) -> Result<()> {
+        match self.durability {
+            Durability::None => Ok(()),
+            Durability::Commit | Durability::Page => self.file.sync_data(),
+        }
+    }
+
+    // Lê bytes de uma posição específica; com mmap a referência aponta direto para o mapeamento
+    pub fn read_at(&mut self, offset: u64, length: usize) -> Result<Cow<'_, [u8]>> {
+        if self.mmap.is_some() {
+            return self.mapped(offset, length).map(Cow::Borrowed);
+        }
+
         self.file.seek(SeekFrom::Start(offset))?;
         let mut buffer = vec![0; length];
         self.file.read_exact(&mut buffer)?;
-        Ok(buffer)
+        Ok(Cow::Owned(buffer))
+    }
+
+    // Grava uma página completa, precedida pelo checksum do seu conteúdo
+    pub fn write_page(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        if data.len() > self.page_data_size() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Conteúdo excede o tamanho de uma página"));
+        }
+
+        let mut page = vec![0; self.page_size];
+        page[CHECKSUM_SIZE..CHECKSUM_SIZE + data.len()].copy_from_slice(data);
+        let checksum = crc32fast::hash(&page[CHECKSUM_SIZE..]);
+        page[..CHECKSUM_SIZE].copy_from_slice(&checksum.to_be_bytes());
+        self.write_at(offset, &page)?;
+
+        // O cache acompanha as escritas, então nunca guarda o conteúdo antigo de uma página reaproveitada
+        self.cache.insert(offset, page.split_off(CHECKSUM_SIZE));
+        self.nodes.remove(&offset);
+        Ok(())
+    }
+
+    // Conteúdo verificado de uma página, sem cópia: com mmap a referência aponta direto para o
+    // mapeamento (que já faz o papel do cache de páginas); sem ele, para o cache de páginas
+    pub fn page(&mut self, offset: u64) -> Result<&[u8]> {
+        if self.mmap.is_some() {
+            let page_size = self.page_size;
+            let page = self.mapped(offset, page_size).map_err(|e| Self::read_error(offset, e))?;
+            return Self::verify_page(offset, page);
+        }
+        if self.cache.touch(&offset) {
+            return Ok(self.cache.peek(&offset).unwrap());
+        }
+
+        let data = self.load_page(offset)?;
+        if self.cache.capacity() == 0 {
+            // Cache desligado: a página fica no buffer até a próxima leitura
+            self.scratch = data;
+            return Ok(&self.scratch);
+        }
+        self.cache.insert(offset, data);
+        Ok(self.cache.peek(&offset).unwrap())
+    }
+
+    // Lê a página do arquivo, sem passar pelo cache, e devolve o seu conteúdo verificado
+    fn load_page(&mut self, offset: u64) -> Result<Vec<u8>> {
+        let page_size = self.page_size;
+        let mut page = self.read_at(offset, page_size).map_err(|e| Self::read_error(offset, e))?.into_owned();
+        Self::verify_page(offset, &page)?;
+        // Remove o checksum no próprio buffer, sem alocar outro
+        page.drain(..CHECKSUM_SIZE);
+        Ok(page)
+    }
+
+    // Confere o checksum de uma página lida e devolve o seu conteúdo
+    fn verify_page(offset: u64, page: &[u8]) -> Result<&[u8]> {
+        let checksum = u32::from_be_bytes(page[..CHECKSUM_SIZE].try_into().unwrap());
+        if checksum != crc32fast::hash(&page[CHECKSUM_SIZE..]) {
+            return Err(CorruptPage::error(offset));
+        }
+        Ok(&page[CHECKSUM_SIZE..])
+    }
+
+    // Uma página truncada no fim do arquivo também é corrupção
+    fn read_error(offset: u64, e: Error) -> Error {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            CorruptPage::error(offset)
+        } else {
+            e
+        }
     }
 
     // Descobre onde escrever o próximo nó (no final do arquivo)
@@ -35,7 +513,394 @@ impl Pager {
         self.file.seek(SeekFrom::End(0))
     }
 
+    // Offset da raiz publicada no cabeçalho (None se a árvore estiver vazia)
+    pub fn root_offset(&self) -> Option<u64> {
+        match self.header.root_offset {
+            0 => None,
+            offset => Some(offset),
+        }
+    }
+
+    // Quantidade de chaves armazenadas, persistida junto com a raiz
+    pub fn key_count(&self) -> u64 {
+        self.header.key_count
+    }
+
+    pub fn set_key_count(&mut self, key_count: u64) {
+        self.header.key_count = key_count;
+    }
+
+    // Sequência da última operação do WAL refletida na raiz publicada
+    pub fn wal_seq(&self) -> u64 {
+        self.header.wal_seq
+    }
+
+    pub fn set_wal_seq(&mut self, wal_seq: u64) {
+        self.header.wal_seq = wal_seq;
+    }
+
+    // Reserva uma página para escrita, reaproveitando uma página livre se houver
+    pub fn allocate(&mut self) -> Result<u64> {
+        let offset = match self.free_pages.pop() {
+            Some(offset) => offset,
+            None => self.get_end_offset()?,
+        };
+        self.allocated.push(offset);
+        Ok(offset)
+    }
+
+    // Como `allocate`, mas uma página tirada do fim do arquivo já passa a fazer parte dele: assim as
+    // alocações seguintes não a devolvem de novo antes que ela seja gravada. Usada quando o offset
+    // precisa ser conhecido antes do conteúdo (ex.: para que a folha anterior aponte para ela)
+    pub fn reserve(&mut self) -> Result<u64> {
+        let offset = self.allocate()?;
+        let end = offset + self.page_size as u64;
+        if self.get_end_offset()? < end {
+            self.file.set_len(end)?;
+        }
+        Ok(offset)
+    }
+
+    // Marca uma página como não mais alcançável a partir da próxima raiz publicada
+    pub fn free(&mut self, offset: u64) {
+        self.pending_free.push(offset);
+    }
+
+    // Libera todas as páginas de uma cadeia de overflow
+    pub fn free_overflow(&mut self, offset: u64) -> Result<()> {
+        for page in self.overflow_pages(offset)? {
+            self.free(page);
+        }
+        Ok(())
+    }
+
+    // Descarta as liberações da operação atual (usado quando a operação falha
+    // e a raiz publicada continua apontando para essas páginas)
+    pub fn discard_pending(&mut self) {
+        self.discard_pending_since(PendingMark::default());
+    }
+
+    // Quantidade de liberações e alocações pendentes; dentro de uma transação marca o início de cada operação
+    pub fn pending_mark(&self) -> PendingMark {
+        PendingMark { freed: self.pending_free.len(), allocated: self.allocated.len() }
+    }
+
+    // Descarta só as liberações feitas depois da marca: as operações anteriores da transação
+    // continuam valendo e a raiz privada ainda aponta para as páginas liberadas pela que falhou.
+    // As páginas alocadas depois da marca não são alcançáveis por raiz publicada nenhuma e voltam para
+    // a lista livre (exceto as que ficaram além do fim do arquivo, que nunca chegaram a ser gravadas).
+    // Um snapshot aberto pode ler a raiz privada de uma transação, então com snapshots abertos elas
+    // ficam retidas como se tivessem sido liberadas pela próxima publicação
+    pub fn discard_pending_since(&mut self, mark: PendingMark) {
+        self.pending_free.truncate(mark.freed);
+        let end = self.get_end_offset().unwrap_or(0);
+        let allocated = self.allocated.split_off(mark.allocated).into_iter().filter(|&page| page < end);
+        if self.pins.borrow().is_empty() {
+            self.free_pages.extend(allocated);
+        } else {
+            let seq = self.header.commit_seq + 1;
+            self.retained.extend(allocated.map(|page| (seq, page)));
+        }
+    }
+
+    // Páginas disponíveis para reaproveitamento imediato
+    pub fn free_page_count(&self) -> usize {
+        self.free_pages.len()
+    }
+
+    // Fixa a publicação atual: nenhuma página liberada a partir da próxima é reaproveitada enquanto
+    // o `Pin` existir. Uma raiz privada (transação aberta) é coberta da mesma forma, já que as páginas
+    // que ela referencia só podem ser liberadas por publicações posteriores
+    pub fn pin(&self) -> Pin {
+        let seq = self.header.commit_seq;
+        *self.pins.borrow_mut().entry(seq).or_default() += 1;
+        Pin { pins: Rc::clone(&self.pins), seq }
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.refs.checkpoints
+    }
+
+    // Acrescenta o checkpoint à tabela, que é regravada e passa a valer na próxima publicação
+    pub fn add_checkpoint(&mut self, checkpoint: Checkpoint) -> Result<()> {
+        self.refs.checkpoints.push(checkpoint);
+        self.write_refs()
+    }
+
+    pub fn branches(&self) -> &[Branch] {
+        &self.refs.branches
+    }
+
+    pub fn current_branch(&self) -> &str {
+        &self.refs.current_branch
+    }
+
+    // Substitui a tabela de branches, que passa a valer na próxima publicação
+    pub fn set_branches(&mut self, branches: Vec<Branch>, current_branch: &str) -> Result<()> {
+        self.refs.branches = branches;
+        self.refs.current_branch = current_branch.to_string();
+        self.write_refs()
+    }
+
+    pub fn save_refs(&self) -> SavedRefs {
+        SavedRefs {
+            refs: self.refs.clone(),
+            pages: self.refs_pages.clone(),
+            offset: self.header.refs_offset,
+            length: self.header.refs_length,
+        }
+    }
+
+    // Volta para a tabela guardada. As páginas da cópia descartada nunca foram publicadas: são
+    // devolvidas pelo `discard_pending` da operação que falhou
+    pub fn restore_refs(&mut self, saved: SavedRefs) {
+        self.preserved = None;
+        self.refs = saved.refs;
+        self.refs_pages = saved.pages;
+        self.header.refs_offset = saved.offset;
+        self.header.refs_length = saved.length;
+    }
+
+    // Regrava a tabela de raízes; a cópia anterior é liberada na publicação
+    fn write_refs(&mut self) -> Result<()> {
+        self.preserved = None;
+        let data = bincode::serialize(&self.refs).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let (offset, pages) = self.write_chain(&data, Vec::new())?;
+        for page in std::mem::replace(&mut self.refs_pages, pages) {
+            self.free(page);
+        }
+        self.header.refs_offset = offset;
+        self.header.refs_length = data.len() as u64;
+        Ok(())
+    }
+
+    // Raízes cujas páginas nunca podem ser liberadas pelas escritas na árvore atual: as dos
+    // checkpoints, as das outras branches e as bases de merge de todas elas
+    fn preserved_roots(&self) -> Vec<Option<u64>> {
+        let checkpoints = self.refs.checkpoints.iter().map(|c| c.root_offset);
+        let branches = self.refs.branches.iter().filter(|b| b.name != self.refs.current_branch).map(|b| b.root_offset);
+        let bases = self.refs.branches.iter().filter(|b| b.parent.is_some()).map(|b| b.base_offset);
+        checkpoints.chain(branches).chain(bases).collect()
+    }
+
+    // Libera as páginas de uma árvore que deixou de ser preservada (a base antiga de um merge), exceto
+    // as que a raiz atual ou alguma raiz ainda preservada alcança e as que a operação atual já liberou
+    // (ex.: as da raiz anterior que o merge copiou). A tabela nova já deve estar gravada
+    pub fn release_tree(&mut self, root: u64, live: Option<u64>) -> Result<()> {
+        let mut reachable: HashSet<u64> = BTree::new(live).pages(self)?.into_iter().collect();
+        reachable.extend(self.preserved()?.iter().copied());
+        reachable.extend(self.pending_free.iter().copied());
+        for page in BTree::new(Some(root)).pages(self)? {
+            if !reachable.contains(&page) {
+                self.free(page);
+            }
+        }
+        Ok(())
+    }
+
+    fn preserved(&mut self) -> Result<&HashSet<u64>> {
+        if self.preserved.is_none() {
+            let mut pages = HashSet::new();
+            for root in self.preserved_roots() {
+                pages.extend(BTree::new(root).pages(self)?);
+            }
+            self.preserved = Some(pages);
+        }
+        Ok(self.preserved.get_or_insert_default())
+    }
+
+    // Publica a nova raiz junto com a lista de páginas livres atualizada. Se a gravação falhar, a
+    // lista livre e o cabeçalho em memória voltam ao que eram: o cabeçalho no arquivo continua
+    // referenciando a árvore e a lista antigas, e nenhuma página delas pode ser reaproveitada
     pub fn update_root_offset(&mut self, root_offset: &[u8;8]) -> Result<()> {
-        self.write_at(0, root_offset)
+        let saved = (
+            self.free_pages.clone(),
+            self.pending_free.clone(),
+            self.retained.clone(),
+            self.free_list_pages.clone(),
+            self.header.clone(),
+        );
+        self.publish_root(root_offset).inspect_err(|_| {
+            (self.free_pages, self.pending_free, self.retained, self.free_list_pages, self.header) = saved;
+        })
     }
-}
\ No newline at end of file
+
+    fn publish_root(&mut self, root_offset: &[u8;8]) -> Result<()> {
+        // As páginas da lista antiga ficam livres assim que o novo cabeçalho for gravado
+        self.pending_free.append(&mut self.free_list_pages);
+
+        // Páginas que algum checkpoint ou outra branch ainda alcança continuam fora da lista livre
+        if !self.refs.checkpoints.is_empty() || !self.refs.branches.is_empty() {
+            self.preserved()?;
+            if let Some(preserved) = &self.preserved {
+                self.pending_free.retain(|page| !preserved.contains(page));
+            }
+        }
+
+        // Páginas retidas que nenhum snapshot aberto alcança mais voltam para a lista livre; as
+        // liberadas agora ficam retidas se algum snapshot fixa uma publicação anterior a esta
+        let seq = self.header.commit_seq + 1;
+        let oldest = self.pins.borrow().keys().next().copied();
+        let (released, retained): (Vec<_>, Vec<_>) =
+            self.retained.drain(..).partition(|&(freed_at, _)| oldest.is_none_or(|pinned| freed_at <= pinned));
+        self.retained = retained;
+        self.free_pages.extend(released.into_iter().map(|(_, page)| page));
+        if oldest.is_some() {
+            self.retained.extend(self.pending_free.drain(..).map(|page| (seq, page)));
+        }
+
+        // As páginas que guardam a nova lista saem da própria lista livre (nunca das liberadas
+        // agora, que o cabeçalho atual ainda referencia) ou do final do arquivo;
+        // reserva até que o restante da lista caiba nelas
+        let mut list_pages = Vec::new();
+        let mut end = self.get_end_offset()?;
+        while self.chain_length(self.free_pages.len() + self.pending_free.len() + self.retained.len()) > list_pages.len() {
+            match self.free_pages.pop() {
+                Some(page) => list_pages.push(page),
+                None => {
+                    list_pages.push(end);
+                    end += self.page_size as u64;
+                }
+            }
+        }
+        self.free_pages.append(&mut self.pending_free);
+
+        let retained = self.retained.iter().map(|(_, page)| page);
+        let data: Vec<u8> = self.free_pages.iter().chain(retained).flat_map(|p| p.to_be_bytes()).collect();
+        let (list_offset, list_pages) = if data.is_empty() && list_pages.is_empty() {
+            (0, Vec::new())
+        } else {
+            self.write_chain(&data, list_pages)?
+        };
+
+        self.header.root_offset = u64::from_be_bytes(*root_offset);
+        self.header.free_list_offset = list_offset;
+        self.header.free_list_length = data.len() as u64;
+        // Os nós e a lista livre precisam estar em disco antes do cabeçalho que os referencia
+        self.sync()?;
+
+        self.header.commit_seq += 1;
+        // Sobrescreve a cópia mais antiga; a cópia da publicação anterior fica intacta
+        let header = self.header.to_bytes();
+        self.write_at(self.header.slot_offset(), &header)?;
+        self.sync()?;
+
+        self.free_list_pages = list_pages;
+        self.allocated.clear();
+        Ok(())
+    }
+
+    // Grava dados maiores que uma página em uma cadeia de páginas de overflow
+    // e retorna o offset da primeira página da cadeia
+    pub fn write_overflow(&mut self, data: &[u8]) -> Result<u64> {
+        Ok(self.write_chain(data, Vec::new())?.0)
+    }
+
+    // Remonta os dados gravados em uma cadeia de páginas de overflow
+    pub fn read_overflow(&mut self, offset: u64, length: usize) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(length);
+        let mut next = offset;
+        let data_size = self.overflow_data_size();
+        while next != 0 && data.len() < length {
+            let page = self.page(next)?;
+            let chunk_len = u32::from_be_bytes(page[8..12].try_into().unwrap()) as usize;
+            data.extend_from_slice(&page[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + chunk_len.min(data_size)]);
+            next = u64::from_be_bytes(page[0..8].try_into().unwrap());
+        }
+
+        if data.len() != length {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Cadeia de overflow terminou antes do tamanho esperado",
+            ));
+        }
+        Ok(data)
+    }
+
+    // Lista os offsets das páginas de uma cadeia de overflow
+    pub fn overflow_pages(&mut self, offset: u64) -> Result<Vec<u64>> {
+        let mut pages = Vec::new();
+        let mut next = offset;
+        while next != 0 {
+            pages.push(next);
+            next = u64::from_be_bytes(self.page(next)?[0..8].try_into().unwrap());
+        }
+        Ok(pages)
+    }
+
+    // Quantidade de páginas de overflow necessárias para guardar `count` offsets
+    fn chain_length(&self, count: usize) -> usize {
+        (count * 8).div_ceil(self.overflow_data_size())
+    }
+
+    // Grava uma cadeia de overflow usando as páginas reservadas em `pages` (ou alocando novas);
+    // páginas reservadas além do necessário ficam vazias no fim da cadeia
+    fn write_chain(&mut self, data: &[u8], mut pages: Vec<u64>) -> Result<(u64, Vec<u64>)> {
+        let mut chunks: Vec<&[u8]> = data.chunks(self.overflow_data_size()).collect();
+        while chunks.len() < pages.len() {
+            chunks.push(&[]);
+        }
+
+        // A cadeia é gravada de trás para frente para que cada página já conheça o offset da próxima
+        let mut next: u64 = 0;
+        let mut written = Vec::new();
+        for chunk in chunks.into_iter().rev() {
+            let mut page = Vec::with_capacity(self.page_data_size());
+            page.extend_from_slice(&next.to_be_bytes());
+            page.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            page.extend_from_slice(chunk);
+
+            next = match pages.pop() {
+                Some(offset) => offset,
+                None => self.allocate()?,
+            };
+            self.write_page(next, &page)?;
+            written.push(next);
+        }
+        Ok((next, written))
+    }
+
+    // Carrega a tabela de raízes nomeadas referenciada pelo cabeçalho
+    fn load_refs(&mut self) -> Result<()> {
+        let table_offset = self.header.refs_offset;
+        if table_offset == 0 {
+            return Ok(());
+        }
+
+        let data = self.read_overflow(table_offset, self.header.refs_length as usize)?;
+        self.refs = bincode::deserialize(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        self.refs_pages = self.overflow_pages(table_offset)?;
+        Ok(())
+    }
+
+    // Carrega a lista de páginas livres persistida no cabeçalho
+    fn load_free_list(&mut self) -> Result<()> {
+        let list_offset = self.header.free_list_offset;
+        let list_length = self.header.free_list_length as usize;
+        if list_offset == 0 {
+            return Ok(());
+        }
+
+        let data = self.read_overflow(list_offset, list_length)?;
+        self.free_pages = data
+            .chunks_exact(8)
+            .map(|p| u64::from_be_bytes(p.try_into().unwrap()))
+            .collect();
+        self.free_list_pages = self.overflow_pages(list_offset)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Pager {
+    // Troca o arquivo por outro aberto sobre o mesmo banco (ex.: somente leitura, para simular
+    // escritas que falham)
+    pub fn set_file(&mut self, file: File) {
+        self.file = file;
+    }
+
+    // Quantidade de cabeçalhos publicados desde a criação do arquivo
+    pub fn commit_seq(&self) -> u64 {
+        self.header.commit_seq
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::io::Result;
+use std::ops::RangeBounds;
+
+use crate::btree::BTree;
+use crate::cursor::Range;
+use crate::pager::{Pager, Pin};
+
+// Visão somente leitura do banco fixada na raiz do momento em que foi criada (ver `Db::snapshot`).
+// Como os nós nunca são sobrescritos, a raiz antiga continua descrevendo uma árvore consistente;
+// o `Pin` impede que o `Db` reaproveite as páginas dela enquanto o snapshot existir.
+// As leituras passam por um paginador próprio, então as escritas no `Db` seguem normalmente
+pub struct Snapshot {
+    pager: Pager,
+    tree: BTree,
+    _pin: Pin,
+}
+
+impl Snapshot {
+    pub fn new(pager: Pager, root: Option<u64>, pin: Pin) -> Self {
+        Snapshot { pager, tree: BTree::new(root), _pin: pin }
+    }
+
+    pub fn search(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.tree.search(key.as_ref(), &mut self.pager)
+    }
+
+    pub fn range<R: RangeBounds<Vec<u8>>>(&mut self, range: R) -> Result<Range<'_>> {
+        self.tree.range(range, &mut self.pager)
+    }
+
+    pub fn scan_prefix(&mut self, prefix: impl AsRef<[u8]>) -> Result<Range<'_>> {
+        self.tree.scan_prefix(prefix.as_ref(), &mut self.pager)
+    }
+
+    // Chaves cujo valor em `other` é diferente do valor neste snapshot, com o valor novo (`None`
+    // quando a chave não existe em `other`). As duas árvores são percorridas em ordem, juntas
+    pub fn diff(&mut self, other: &mut Snapshot) -> Result<BTreeMap<Vec<u8>, Option<Vec<u8>>>> {
+        let mut changes = BTreeMap::new();
+        if self.tree.root == other.tree.root {
+            return Ok(changes);
+        }
+
+        let mut old = self.range(..)?.peekable();
+        let mut new = other.range(..)?.peekable();
+        loop {
+            let (old_key, new_key) = match (old.peek(), new.peek()) {
+                (None, None) => break,
+                (Some(Err(_)), _) => return Err(old.next().unwrap().unwrap_err()),
+                (_, Some(Err(_))) => return Err(new.next().unwrap().unwrap_err()),
+                (Some(Ok((k, _))), None) => (Some(k.clone()), None),
+                (None, Some(Ok((k, _)))) => (None, Some(k.clone())),
+                (Some(Ok((a, _))), Some(Ok((b, _)))) => (Some(a.clone()), Some(b.clone())),
+            };
+
+            match (old_key, new_key) {
+                (Some(a), Some(b)) if a == b => {
+                    let (_, old_value) = old.next().unwrap()?;
+                    let (key, new_value) = new.next().unwrap()?;
+                    if old_value != new_value {
+                        changes.insert(key, Some(new_value));
+                    }
+                }
+                (Some(a), b) if b.as_ref().is_none_or(|b| a < *b) => {
+                    old.next();
+                    changes.insert(a, None);
+                }
+                _ => {
+                    let (key, value) = new.next().unwrap()?;
+                    changes.insert(key, Some(value));
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+use crate::batch::WriteBatch;
+use crate::pager::Durability;
+
+// Cabeçalho de cada registro do log: tamanho do corpo (u32) + CRC32 do corpo (u32)
+const RECORD_HEADER_SIZE: usize = 8;
+
+// Operação lógica gravada no log antes de ser aplicada à árvore
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WalRecord {
+    Insert { key: Vec<u8>, value: Vec<u8> },
+    Delete { key: Vec<u8> },
+    // Lote registrado inteiro: na recuperação é refeito por completo ou recusado por completo
+    Batch(WriteBatch),
+}
+
+// Log de escrita antecipada (write-ahead log) de um banco de dados.
+// Cada operação é registrada e sincronizada em disco antes de ser aplicada; se o
+// processo cair no meio da aplicação, o registro é refeito na próxima abertura.
+pub struct Wal {
+    file: File,
+    next_seq: u64,
+    durability: Durability,
+}
+
+impl Wal {
+    // Abre (ou cria) o log ao lado do arquivo do banco; `last_seq` é a última sequência já publicada
+    pub fn open(db_name: &str, last_seq: u64) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(format!("./databases/{db_name}.wal"))?;
+
+        Ok(Wal { file, next_seq: last_seq + 1, durability: Durability::default() })
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    // Registra uma operação e só retorna depois que ela estiver em disco
+    // (exceto com a durabilidade desligada)
+    pub fn append(&mut self, record: &WalRecord) -> Result<u64> {
+        let seq = self.next_seq;
+
+        let mut body = seq.to_be_bytes().to_vec();
+        body.extend(bincode::serialize(record).map_err(|e| Error::new(ErrorKind::InvalidData, e))?);
+
+        let mut data = Vec::with_capacity(RECORD_HEADER_SIZE + body.len());
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+        data.extend_from_slice(&body);
+
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&data)?;
+        if self.durability != Durability::None {
+            self.file.sync_data()?;
+        }
+
+        self.next_seq += 1;
+        Ok(seq)
+    }
+
+    // Lê os registros completos do log; um registro final incompleto ou com
+    // checksum inválido (escrita interrompida) é descartado junto com o que vier depois
+    pub fn read_records(&mut self) -> Result<Vec<(u64, WalRecord)>> {
+        let mut data = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut data)?;
+
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos + RECORD_HEADER_SIZE <= data.len() {
+            let length = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let checksum = u32::from_be_bytes(data[pos + 4..pos + 8].try_into().unwrap());
+            let start = pos + RECORD_HEADER_SIZE;
+            if length < 8 || start + length > data.len() {
+                break;
+            }
+
+            let body = &data[start..start + length];
+            if crc32fast::hash(body) != checksum {
+                break;
+            }
+            let Ok(record) = bincode::deserialize(&body[8..]) else {
+                break;
+            };
+
+            let seq = u64::from_be_bytes(body[..8].try_into().unwrap());
+            self.next_seq = self.next_seq.max(seq + 1);
+            records.push((seq, record));
+            pos = start + length;
+        }
+
+        Ok(records)
+    }
+
+    // Esvazia o log depois que as operações registradas foram publicadas no banco
+    pub fn reset(&mut self) -> Result<()> {
+        self.file.set_len(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::MAX_KEY_SIZE;
+    use crate::db::{Db, create_database};
+    use crate::pager::Layout;
+    use std::fs;
+    use std::path::Path;
+
+    fn setup_test(db_name: &str) {
+        teardown_test(db_name);
+        create_database(db_name, Layout::default()).expect("Não foi possível criar o banco de dados de teste.");
+    }
+
+    fn teardown_test(db_name: &str) {
+        for ext in ["kvdb", "wal"] {
+            let filename = format!("./databases/{db_name}.{ext}");
+            if Path::new(&filename).exists() {
+                fs::remove_file(&filename).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_logged_operations() {
+        setup_test("test_wal_replay");
+
+        let mut db = Db::open("test_wal_replay").unwrap();
+        db.insert("Key1".to_string(), "Val1".to_string()).unwrap();
+        db.insert("Key2".to_string(), "Val2".to_string()).unwrap();
+        drop(db);
+
+        // Simula uma queda logo depois de registrar as operações, antes de aplicá-las
+        let mut wal = Wal::open("test_wal_replay", 2).unwrap();
+        wal.append(&WalRecord::Delete { key: b"Key1".to_vec() }).unwrap();
+        wal.append(&WalRecord::Insert { key: b"Key3".to_vec(), value: b"Val3".to_vec() }).unwrap();
+        drop(wal);
+
+        let mut db = Db::open("test_wal_replay").unwrap();
+        assert_eq!(db.search("Key1").unwrap(), None);
+        assert_eq!(db.search("Key2").unwrap(), Some(b"Val2".to_vec()));
+        assert_eq!(db.search("Key3").unwrap(), Some(b"Val3".to_vec()));
+
+        teardown_test("test_wal_replay");
+    }
+
+    #[test]
+    fn test_discard_torn_record() {
+        setup_test("test_wal_torn");
+
+        let mut wal = Wal::open("test_wal_torn", 0).unwrap();
+        wal.append(&WalRecord::Insert { key: b"Key1".to_vec(), value: b"Val1".to_vec() }).unwrap();
+        wal.append(&WalRecord::Insert { key: b"Key2".to_vec(), value: b"Val2".to_vec() }).unwrap();
+        drop(wal);
+
+        // Corta o último registro no meio, como uma escrita interrompida
+        let filename = "./databases/test_wal_torn.wal";
+        let length = fs::metadata(filename).unwrap().len();
+        fs::OpenOptions::new().write(true).open(filename).unwrap().set_len(length - 3).unwrap();
+
+        let mut db = Db::open("test_wal_torn").unwrap();
+        assert_eq!(db.search("Key1").unwrap(), Some(b"Val1".to_vec()));
+        assert_eq!(db.search("Key2").unwrap(), None);
+        assert_eq!(fs::metadata(filename).unwrap().len(), 0);
+
+        teardown_test("test_wal_torn");
+    }
+
+    #[test]
+    fn test_replay_batch_as_a_unit() {
+        setup_test("test_wal_batch");
+
+        let mut db = Db::open("test_wal_batch").unwrap();
+        db.insert("Key1".to_string(), "Val1".to_string()).unwrap();
+        drop(db);
+
+        // Lote registrado antes da queda: refeito por inteiro
+        let mut wal = Wal::open("test_wal_batch", 1).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.delete("Key1".to_string()).put("Key2".to_string(), "Val2".to_string());
+        wal.append(&WalRecord::Batch(batch)).unwrap();
+        // Lote com uma chave inválida: recusado por inteiro, inclusive a inserção válida
+        let mut batch = WriteBatch::new();
+        batch.put("Key3".to_string(), "Val3".to_string()).put("k".repeat(MAX_KEY_SIZE + 1), String::new());
+        wal.append(&WalRecord::Batch(batch)).unwrap();
+        drop(wal);
+
+        let mut db = Db::open("test_wal_batch").unwrap();
+        assert_eq!(db.search("Key1").unwrap(), None);
+        assert_eq!(db.search("Key2").unwrap(), Some(b"Val2".to_vec()));
+        assert_eq!(db.search("Key3").unwrap(), None);
+        assert_eq!(db.len().unwrap(), 1);
+
+        teardown_test("test_wal_batch");
+    }
+}